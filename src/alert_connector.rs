@@ -1,6 +1,14 @@
+use crate::alert_status::AlertStatus;
+use crate::group_assignment::GroupAssignment;
+use crate::requirements::Requirements;
+use crate::roster_entry::RosterEntry;
+use crate::roster_order::RosterOrder;
+use crate::roster_role::{RosterRole, ROSTER_ROLES};
+use crate::waitlist_entry::WaitlistEntry;
 use crate::war_message::WarMessage;
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Clone, Deserialize, Serialize, Default)]
 pub struct AlertConnector {
@@ -11,26 +19,73 @@ pub struct AlertConnector {
     pub faction: String,
     pub territory: String,
     pub title: String,
+    /// Free text shown in the embed of custom events. Empty for wars.
+    #[serde(default)]
+    pub description: String,
     #[serde(rename = "type")]
     pub r#type: u8,
     #[serde(default)]
-    pub tanks: Vec<u64>,
+    pub tanks: Vec<RosterEntry>,
     #[serde(default)]
-    pub erdps: Vec<u64>,
+    pub erdps: Vec<RosterEntry>,
     #[serde(default)]
-    pub prdps: Vec<u64>,
+    pub prdps: Vec<RosterEntry>,
     #[serde(default)]
-    pub mdps: Vec<u64>,
+    pub mdps: Vec<RosterEntry>,
     #[serde(default)]
-    pub healers: Vec<u64>,
+    pub healers: Vec<RosterEntry>,
     #[serde(default)]
-    pub tentative: Vec<u64>,
+    pub tentative: Vec<RosterEntry>,
     #[serde(rename = "notAvailable", default)]
-    pub not_available: Vec<u64>,
+    pub not_available: Vec<RosterEntry>,
+    #[serde(default)]
+    pub artillery: Vec<RosterEntry>,
+    /// Unix timestamp of the war start. Missing on alerts created before it was stored.
+    #[serde(rename = "startsAt", default)]
+    pub starts_at: Option<i64>,
+    #[serde(default)]
+    pub status: AlertStatus,
+    /// The maximum number of members per role. Roles without a quota are unlimited.
+    #[serde(default)]
+    pub quotas: HashMap<RosterRole, u32>,
+    /// What members need to sign up for a combat role.
+    #[serde(default)]
+    pub requirements: Requirements,
+    /// Members waiting for a spot in a full role, in the order they signed up.
+    #[serde(default)]
+    pub waitlist: Vec<WaitlistEntry>,
+    /// Members officers pinned into a group of the army or onto the bench.
     #[serde(default)]
-    pub artillery: Vec<u64>,
+    pub assignments: Vec<GroupAssignment>,
+    /// How members are listed in the embed.
+    #[serde(rename = "rosterOrder", default)]
+    pub roster_order: RosterOrder,
+    /// Increased on every roster change, so concurrent changes don't overwrite each other.
+    #[serde(default)]
+    pub revision: i64,
     #[serde(rename = "warMessages")]
     pub war_messages: Vec<WarMessage>,
+    /// The messages the army layout was posted as, at most one per channel.
+    #[serde(rename = "armyMessages", default)]
+    pub army_messages: Vec<WarMessage>,
+}
+
+/// A waitlisted member that was moved into the role they waited for.
+pub struct Promotion {
+    pub user_id: u64,
+    pub role: RosterRole,
+    /// The role they held while waiting, if any.
+    pub previous: Option<RosterRole>,
+}
+
+/// What a signup or withdrawal changed on the roster.
+#[derive(Default)]
+pub struct RosterChange {
+    /// The role the user was moved out of.
+    pub previous: Option<RosterRole>,
+    /// The user's place on the waitlist when the role was full, starting at 1.
+    pub waitlisted: Option<usize>,
+    pub promoted: Vec<Promotion>,
 }
 
 impl PartialEq for AlertConnector {
@@ -40,19 +95,112 @@ impl PartialEq for AlertConnector {
 }
 
 impl AlertConnector {
+    /// The title shown in embeds and notifications. Wars have no stored title.
+    pub fn get_title(&self) -> &str {
+        if self.title.is_empty() {
+            "War Alert"
+        } else {
+            &self.title
+        }
+    }
+
+    /// When the war starts, as a Discord timestamp that every member sees in their own time
+    /// zone. Alerts created before the start was stored fall back to the display strings.
+    pub fn get_start_label(&self) -> String {
+        if let Some(starts_at) = self.starts_at {
+            format!("<t:{}:F>", starts_at)
+        } else {
+            format!("{} at {}", self.date.trim(), self.time)
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.status == AlertStatus::Open
+    }
+
+    /// Everyone who responded to the alert, including waitlisted members.
     pub fn get_users(&self) -> Vec<u64> {
-        self.tanks
-            .iter()
-            .chain(&self.erdps)
-            .chain(&self.prdps)
-            .chain(&self.mdps)
-            .chain(&self.healers)
-            .chain(&self.tentative)
-            .chain(&self.not_available)
-            .copied()
+        ROSTER_ROLES
+            .into_iter()
+            .flat_map(|role| self.get_role(role))
+            .map(|entry| entry.user_id)
+            .chain(self.waitlist.iter().map(|entry| entry.user_id))
             .collect()
     }
 
+    /// Finds users that are listed under more than one role, along with the first role they
+    /// appear in. A user should only ever hold a single role on an alert.
+    pub fn get_users_with_multiple_roles(&self) -> Vec<(u64, RosterRole)> {
+        let mut seen = HashSet::new();
+        let mut result = vec![];
+        for user_id in ROSTER_ROLES
+            .into_iter()
+            .flat_map(|role| self.get_role(role))
+            .map(|entry| entry.user_id)
+        {
+            if !seen.insert(user_id) && !result.iter().any(|&(id, _)| id == user_id) {
+                if let Some(role) = self.get_user_role(user_id) {
+                    result.push((user_id, role));
+                }
+            }
+        }
+        result
+    }
+
+    /// Keeps every user only in the first role they are listed in. Rosters stored by older
+    /// versions could list a user several times. Sign up times, `added_by` and the waitlist stay
+    /// as they are.
+    pub fn remove_duplicate_entries(&mut self) {
+        let mut seen = HashSet::new();
+        for role in ROSTER_ROLES {
+            self.get_role_mut(role)
+                .retain(|entry| seen.insert(entry.user_id));
+        }
+    }
+
+    pub fn get_role(&self, role: RosterRole) -> &[RosterEntry] {
+        match role {
+            RosterRole::Tank => &self.tanks,
+            RosterRole::Mdps => &self.mdps,
+            RosterRole::Prdps => &self.prdps,
+            RosterRole::Erdps => &self.erdps,
+            RosterRole::Healer => &self.healers,
+            RosterRole::Artillery => &self.artillery,
+            RosterRole::Tentative => &self.tentative,
+            RosterRole::NotAvailable => &self.not_available,
+        }
+    }
+
+    pub fn get_role_mut(&mut self, role: RosterRole) -> &mut Vec<RosterEntry> {
+        match role {
+            RosterRole::Tank => &mut self.tanks,
+            RosterRole::Mdps => &mut self.mdps,
+            RosterRole::Prdps => &mut self.prdps,
+            RosterRole::Erdps => &mut self.erdps,
+            RosterRole::Healer => &mut self.healers,
+            RosterRole::Artillery => &mut self.artillery,
+            RosterRole::Tentative => &mut self.tentative,
+            RosterRole::NotAvailable => &mut self.not_available,
+        }
+    }
+
+    pub fn get_user_role(&self, user_id: u64) -> Option<RosterRole> {
+        ROSTER_ROLES
+            .into_iter()
+            .find(|&role| self.has_role(user_id, role))
+    }
+
+    pub fn has_combat_role(&self, user_id: u64) -> bool {
+        self.get_user_role(user_id)
+            .is_some_and(|role| role.is_combat_role())
+    }
+
+    pub fn has_role(&self, user_id: u64, role: RosterRole) -> bool {
+        self.get_role(role)
+            .iter()
+            .any(|entry| entry.user_id == user_id)
+    }
+
     pub fn get_guild_ids(&self) -> HashSet<u64> {
         let mut result = HashSet::new();
         for war_message in &self.war_messages {
@@ -63,7 +211,7 @@ impl AlertConnector {
 
     pub fn contains_war_message(&self, guild_id: u64, channel_id: u64, message_id: u64) -> bool {
         let wm = WarMessage::new(guild_id, channel_id, message_id);
-        self.war_messages.iter().any(|&wm2| wm2 == wm)
+        self.war_messages.contains(&wm)
     }
 
     pub fn channel_contains_war_message(&self, guild_id: u64, channel_id: u64) -> bool {
@@ -71,4 +219,246 @@ impl AlertConnector {
             .iter()
             .any(|wm| wm.get_channel_id() == channel_id && wm.get_guild_id() == guild_id)
     }
+
+    pub fn get_assignment(&self, user_id: u64) -> Option<GroupAssignment> {
+        self.assignments
+            .iter()
+            .find(|assignment| assignment.user_id == user_id)
+            .copied()
+    }
+
+    /// The members pinned into `group`, or onto the bench for `None`.
+    pub fn get_assigned(&self, group: Option<u8>) -> Vec<u64> {
+        self.assignments
+            .iter()
+            .filter(|assignment| assignment.group == group)
+            .map(|assignment| assignment.user_id)
+            .collect()
+    }
+
+    pub fn get_quota(&self, role: RosterRole) -> Option<u32> {
+        self.quotas.get(&role).copied()
+    }
+
+    pub fn is_full(&self, role: RosterRole) -> bool {
+        self.get_quota(role)
+            .is_some_and(|quota| self.get_role(role).len() >= quota as usize)
+    }
+
+    /// The role the user is waiting for and their place in its queue, starting at 1.
+    pub fn get_waitlist_position(&self, user_id: u64) -> Option<(RosterRole, usize)> {
+        let role = self
+            .waitlist
+            .iter()
+            .find(|entry| entry.user_id == user_id)?
+            .role;
+        let position = self
+            .waitlist
+            .iter()
+            .filter(|entry| entry.role == role)
+            .position(|entry| entry.user_id == user_id)?;
+        Some((role, position + 1))
+    }
+
+    /// Moves the user into `role`, or onto its waitlist if the role is full. A user holds at
+    /// most one role and one waitlist spot. `added_by` is the officer signing the user up, `None`
+    /// if the user signed up themselves.
+    pub fn signup(
+        &mut self,
+        user_id: u64,
+        role: RosterRole,
+        added_by: Option<u64>,
+    ) -> RosterChange {
+        let mut change = RosterChange::default();
+        // Holding the role already changes nothing, a spot on another role's waitlist is kept.
+        if self.has_role(user_id, role) {
+            return change;
+        }
+        let now = Utc::now().timestamp();
+        if self.is_full(role) {
+            if !self
+                .waitlist
+                .iter()
+                .any(|entry| entry.user_id == user_id && entry.role == role)
+            {
+                self.waitlist.retain(|entry| entry.user_id != user_id);
+                self.waitlist
+                    .push(WaitlistEntry::new(user_id, role, now, added_by));
+            }
+            change.waitlisted = self
+                .get_waitlist_position(user_id)
+                .map(|(_, position)| position);
+            return change;
+        }
+
+        self.waitlist.retain(|entry| entry.user_id != user_id);
+        change.previous = self.move_into_role(user_id, role, now, added_by);
+        self.promote_waitlist(&mut change.promoted);
+        change
+    }
+
+    /// Takes the user out of `role` and off its waitlist, then fills freed spots.
+    pub fn withdraw(&mut self, user_id: u64, role: RosterRole) -> RosterChange {
+        let mut change = RosterChange::default();
+        self.get_role_mut(role)
+            .retain(|entry| entry.user_id != user_id);
+        self.waitlist
+            .retain(|entry| !(entry.user_id == user_id && entry.role == role));
+        self.promote_waitlist(&mut change.promoted);
+        change
+    }
+
+    /// Moves waitlisted members into roles with free spots, first come first served.
+    fn promote_waitlist(&mut self, promoted: &mut Vec<Promotion>) {
+        while let Some(index) = self
+            .waitlist
+            .iter()
+            .position(|entry| !self.is_full(entry.role))
+        {
+            let entry = self.waitlist.remove(index);
+            let previous = self.move_into_role(
+                entry.user_id,
+                entry.role,
+                entry.waiting_since,
+                entry.added_by,
+            );
+            promoted.push(Promotion {
+                user_id: entry.user_id,
+                role: entry.role,
+                previous,
+            });
+        }
+    }
+
+    /// Puts the user into `role` and takes them out of every other one, keeping their signup time
+    /// and recording the role change. Users new to the roster count as signed up at
+    /// `signed_up_at`. Returns the role they were taken out of.
+    fn move_into_role(
+        &mut self,
+        user_id: u64,
+        role: RosterRole,
+        signed_up_at: i64,
+        added_by: Option<u64>,
+    ) -> Option<RosterRole> {
+        let previous = self
+            .get_user_role(user_id)
+            .filter(|&previous| previous != role);
+        let mut moved = None;
+        for other in ROSTER_ROLES.into_iter().filter(|&other| other != role) {
+            let list = self.get_role_mut(other);
+            if let Some(index) = list.iter().position(|entry| entry.user_id == user_id) {
+                let mut removed = list.remove(index);
+                removed.change_role(other, Utc::now().timestamp());
+                moved.get_or_insert(removed);
+            }
+            list.retain(|entry| entry.user_id != user_id);
+        }
+        if !self.has_role(user_id, role) {
+            let entry = moved.unwrap_or_else(|| RosterEntry::new(user_id, signed_up_at));
+            self.get_role_mut(role).push(entry);
+        }
+        // Rosters stored by older versions can list the user twice, only the first entry stays.
+        let list = self.get_role_mut(role);
+        if let Some(first) = list.iter().position(|entry| entry.user_id == user_id) {
+            list[first].added_by = added_by;
+            let mut index = 0;
+            list.retain(|entry| {
+                index += 1;
+                entry.user_id != user_id || index - 1 == first
+            });
+        }
+        previous
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_users_includes_artillery_and_the_waitlist() {
+        let ac = AlertConnector {
+            tanks: vec![RosterEntry::new(1, 0)],
+            artillery: vec![RosterEntry::new(2, 0)],
+            waitlist: vec![WaitlistEntry::new(3, RosterRole::Healer, 0, None)],
+            ..Default::default()
+        };
+        assert_eq!(ac.get_users(), vec![1, 2, 3]);
+        assert_eq!(ac.get_user_role(2), Some(RosterRole::Artillery));
+    }
+
+    #[test]
+    fn signup_holds_the_user_to_one_role() {
+        let mut ac = AlertConnector::default();
+        assert_eq!(ac.signup(1, RosterRole::Tank, None).previous, None);
+        let change = ac.signup(1, RosterRole::Artillery, None);
+        assert_eq!(change.previous, Some(RosterRole::Tank));
+        assert!(ac.tanks.is_empty());
+        assert!(ac.has_role(1, RosterRole::Artillery));
+        assert!(ac.get_users_with_multiple_roles().is_empty());
+
+        // Signing up again for the same role changes nothing.
+        assert_eq!(ac.signup(1, RosterRole::Artillery, None).previous, None);
+        assert_eq!(ac.artillery.len(), 1);
+    }
+
+    #[test]
+    fn signing_up_for_the_held_role_keeps_the_waitlist_spot() {
+        let mut ac = AlertConnector {
+            quotas: HashMap::from([(RosterRole::Healer, 1)]),
+            ..Default::default()
+        };
+        ac.signup(1, RosterRole::Healer, None);
+        ac.signup(2, RosterRole::Tank, None);
+        assert_eq!(ac.signup(2, RosterRole::Healer, None).waitlisted, Some(1));
+
+        ac.signup(2, RosterRole::Tank, Some(10));
+        assert_eq!(ac.get_waitlist_position(2), Some((RosterRole::Healer, 1)));
+        assert_eq!(ac.tanks[0].added_by, None);
+    }
+
+    #[test]
+    fn full_roles_waitlist_and_promote_in_order() {
+        let mut ac = AlertConnector {
+            quotas: HashMap::from([(RosterRole::Healer, 1)]),
+            ..Default::default()
+        };
+        assert_eq!(ac.signup(1, RosterRole::Healer, None).waitlisted, None);
+        assert_eq!(ac.signup(2, RosterRole::Healer, None).waitlisted, Some(1));
+        assert_eq!(ac.signup(3, RosterRole::Healer, None).waitlisted, Some(2));
+        assert!(!ac.has_role(2, RosterRole::Healer));
+
+        let change = ac.withdraw(1, RosterRole::Healer);
+        assert_eq!(change.promoted.len(), 1);
+        assert_eq!(change.promoted[0].user_id, 2);
+        assert!(ac.has_role(2, RosterRole::Healer));
+        assert_eq!(
+            ac.get_waitlist_position(3).map(|(_, position)| position),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn proxy_signups_record_the_officer() {
+        let mut ac = AlertConnector {
+            quotas: HashMap::from([(RosterRole::Tank, 1)]),
+            ..Default::default()
+        };
+        ac.signup(1, RosterRole::Tank, Some(10));
+        assert_eq!(ac.tanks[0].added_by, Some(10));
+        ac.signup(2, RosterRole::Tank, Some(10));
+        assert_eq!(ac.waitlist[0].added_by, Some(10));
+
+        // The officer stays recorded when the waitlisted member is moved in.
+        ac.withdraw(1, RosterRole::Tank);
+        assert_eq!(ac.tanks.len(), 1);
+        assert_eq!(ac.tanks[0].user_id, 2);
+        assert_eq!(ac.tanks[0].added_by, Some(10));
+
+        ac.withdraw(2, RosterRole::Tank);
+        assert!(ac.tanks.is_empty());
+        // Signing up themselves later clears it.
+        ac.signup(2, RosterRole::Tank, None);
+        assert_eq!(ac.tanks[0].added_by, None);
+    }
 }
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+/// The lifecycle state of an alert. Only open alerts accept signups.
+#[derive(Copy, Clone, Eq, PartialEq, Default, Hash, Serialize, Deserialize, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub enum AlertStatus {
+    #[default]
+    Open,
+    /// Signups are locked because the war is about to start.
+    Closed,
+    /// The war is over. Archived alerts are no longer refreshed.
+    Archived,
+    Cancelled,
+}
+
+impl From<AlertStatus> for String {
+    fn from(status: AlertStatus) -> Self {
+        match status {
+            AlertStatus::Open => "open".to_string(),
+            AlertStatus::Closed => "closed".to_string(),
+            AlertStatus::Archived => "archived".to_string(),
+            AlertStatus::Cancelled => "cancelled".to_string(),
+        }
+    }
+}
+
+impl TryFrom<String> for AlertStatus {
+    type Error = &'static str;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "open" => Ok(AlertStatus::Open),
+            "closed" => Ok(AlertStatus::Closed),
+            "archived" => Ok(AlertStatus::Archived),
+            "cancelled" => Ok(AlertStatus::Cancelled),
+            _ => Err("Failed to parse alert status"),
+        }
+    }
+}
+
+impl AlertStatus {
+    pub fn get_label(&self) -> &'static str {
+        match self {
+            AlertStatus::Open => "Open",
+            AlertStatus::Closed => "Signups closed",
+            AlertStatus::Archived => "Finished",
+            AlertStatus::Cancelled => "CANCELLED",
+        }
+    }
+}
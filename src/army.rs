@@ -0,0 +1,232 @@
+use crate::alert_connector::AlertConnector;
+use crate::roster_role::{RosterRole, ROSTER_ROLES};
+use crate::user_data::UserData;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+pub const GROUP_COUNT: usize = 10;
+pub const GROUP_SIZE: usize = 5;
+
+/// The combat roster of an alert laid out in groups of five.
+pub struct Army {
+    pub groups: Vec<Vec<u64>>,
+    /// Members on standby, followed by those that didn't fit into the groups in signup order.
+    pub bench: Vec<u64>,
+}
+
+impl Army {
+    /// Builds a balanced layout from the combat roles of `ac`. Members pinned by officers keep
+    /// their group or bench spot. Artillery shares the groups at the back, every other group gets
+    /// a tank and a healer, and everyone else is spread so the groups end up with similar gear
+    /// scores. `user_data` holds the registered members.
+    pub fn build(ac: &AlertConnector, user_data: &HashMap<u64, UserData>) -> Self {
+        let mut members: Vec<_> = ROSTER_ROLES
+            .into_iter()
+            .filter(|role| role.is_combat_role())
+            .flat_map(|role| ac.get_role(role).iter().map(move |entry| (entry, role)))
+            .collect();
+        members.sort_by_key(|(entry, _)| entry.signed_up_at);
+
+        let mut groups = vec![vec![]; GROUP_COUNT];
+        let mut bench = vec![];
+        members.retain(|(entry, _)| {
+            match ac
+                .get_assignment(entry.user_id)
+                .map(|assignment| assignment.group)
+            {
+                Some(Some(group)) => {
+                    match (group as usize)
+                        .checked_sub(1)
+                        .and_then(|index| groups.get_mut(index))
+                    {
+                        Some(group) if group.len() < GROUP_SIZE => group.push(entry.user_id),
+                        _ => return true,
+                    }
+                }
+                Some(None) => bench.push(entry.user_id),
+                None => return true,
+            }
+            false
+        });
+
+        // Members beyond the free slots are benched, first come first served.
+        let free = groups
+            .iter()
+            .map(|group| GROUP_SIZE - group.len())
+            .sum::<usize>();
+        bench.extend(
+            members
+                .split_off(members.len().min(free))
+                .into_iter()
+                .map(|(entry, _)| entry.user_id),
+        );
+
+        let strength = |user_id: &u64| {
+            user_data.get(user_id).map_or((0, 0), |user_data| {
+                (user_data.gear_score, user_data.get_main_hand_level())
+            })
+        };
+        // The members of `role`, strongest first.
+        let take = |role: RosterRole| {
+            let mut user_ids: Vec<u64> = members
+                .iter()
+                .filter(|&&(_, member_role)| member_role == role)
+                .map(|(entry, _)| entry.user_id)
+                .collect();
+            user_ids.sort_by_key(|user_id| Reverse(strength(user_id)));
+            user_ids
+        };
+        let has_role = |group: &Vec<u64>, role: RosterRole| {
+            group.iter().any(|&user_id| ac.has_role(user_id, role))
+        };
+
+        for user_id in take(RosterRole::Artillery) {
+            if let Some(group) = groups
+                .iter_mut()
+                .rev()
+                .find(|group| group.len() < GROUP_SIZE)
+            {
+                group.push(user_id);
+            }
+        }
+
+        let mut tanks = take(RosterRole::Tank).into_iter();
+        let mut healers = take(RosterRole::Healer).into_iter();
+        for group in groups
+            .iter_mut()
+            .filter(|group| !has_role(group, RosterRole::Artillery))
+        {
+            if !has_role(group, RosterRole::Tank) && group.len() < GROUP_SIZE {
+                group.extend(tanks.next());
+            }
+            if !has_role(group, RosterRole::Healer) && group.len() < GROUP_SIZE {
+                group.extend(healers.next());
+            }
+        }
+
+        let mut rest: Vec<u64> = [RosterRole::Mdps, RosterRole::Prdps, RosterRole::Erdps]
+            .into_iter()
+            .flat_map(take)
+            .chain(tanks)
+            .chain(healers)
+            .collect();
+        rest.sort_by_key(|user_id| Reverse(strength(user_id)));
+        for user_id in rest {
+            // Frontline groups are filled first, the one with the lowest gear score so far wins.
+            if let Some(group) = groups
+                .iter_mut()
+                .filter(|group| group.len() < GROUP_SIZE)
+                .min_by_key(|group| {
+                    (
+                        has_role(group, RosterRole::Artillery),
+                        group
+                            .iter()
+                            .map(|user_id| strength(user_id).0 as u32)
+                            .sum::<u32>(),
+                    )
+                })
+            {
+                group.push(user_id);
+            }
+        }
+
+        Self { groups, bench }
+    }
+
+    /// The group number of the user starting at 1, or `None` if they are not in a group.
+    pub fn get_group(&self, user_id: u64) -> Option<usize> {
+        self.groups
+            .iter()
+            .position(|group| group.contains(&user_id))
+            .map(|index| index + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group_assignment::GroupAssignment;
+    use crate::roster_entry::RosterEntry;
+
+    /// Entries for the users in `ids`, signed up in that order.
+    fn entries(ids: std::ops::Range<u64>) -> Vec<RosterEntry> {
+        ids.map(|user_id| RosterEntry::new(user_id, user_id as i64))
+            .collect()
+    }
+
+    #[test]
+    fn frontline_groups_get_a_tank_and_a_healer() {
+        let ac = AlertConnector {
+            tanks: entries(0..10),
+            healers: entries(10..20),
+            mdps: entries(20..50),
+            ..Default::default()
+        };
+        let army = Army::build(&ac, &HashMap::new());
+        for group in &army.groups {
+            assert_eq!(group.len(), GROUP_SIZE);
+            for role in [RosterRole::Tank, RosterRole::Healer] {
+                let count = group
+                    .iter()
+                    .filter(|&&user_id| ac.has_role(user_id, role))
+                    .count();
+                assert_eq!(count, 1);
+            }
+        }
+        assert!(army.bench.is_empty());
+    }
+
+    #[test]
+    fn artillery_is_grouped_at_the_back() {
+        let ac = AlertConnector {
+            tanks: entries(0..10),
+            healers: entries(10..20),
+            artillery: entries(20..27),
+            ..Default::default()
+        };
+        let army = Army::build(&ac, &HashMap::new());
+        assert_eq!(army.groups[GROUP_COUNT - 1], (20..25).collect::<Vec<_>>());
+        assert!(army.groups[GROUP_COUNT - 2].contains(&25));
+        assert!(army.groups[GROUP_COUNT - 2].contains(&26));
+        for user_id in 20..27 {
+            assert!(army.get_group(user_id).unwrap() >= GROUP_COUNT - 1);
+        }
+        // The frontline groups are the ones holding the tanks.
+        for group in &army.groups[..GROUP_COUNT - 2] {
+            assert!(group
+                .iter()
+                .any(|&user_id| ac.has_role(user_id, RosterRole::Tank)));
+        }
+    }
+
+    #[test]
+    fn pinned_groups_and_bench_spots_are_kept() {
+        let ac = AlertConnector {
+            tanks: entries(0..2),
+            mdps: entries(2..10),
+            assignments: vec![
+                GroupAssignment::new(2, Some(3)),
+                GroupAssignment::new(0, None),
+            ],
+            ..Default::default()
+        };
+        let army = Army::build(&ac, &HashMap::new());
+        assert_eq!(army.get_group(2), Some(3));
+        assert_eq!(army.get_group(0), None);
+        assert_eq!(army.bench, vec![0]);
+    }
+
+    #[test]
+    fn members_past_the_last_group_are_benched_in_signup_order() {
+        let ac = AlertConnector {
+            mdps: entries(0..55),
+            ..Default::default()
+        };
+        let army = Army::build(&ac, &HashMap::new());
+        assert_eq!(
+            army.groups.iter().map(Vec::len).sum::<usize>(),
+            GROUP_COUNT * GROUP_SIZE
+        );
+        assert_eq!(army.bench, (50..55).collect::<Vec<_>>());
+    }
+}
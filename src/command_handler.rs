@@ -1,24 +1,354 @@
+use crate::alert_connector::AlertConnector;
+use crate::alert_status::AlertStatus;
+use crate::army::{GROUP_COUNT, GROUP_SIZE};
+use crate::component_handler::{add_signup_components, SignupMode};
 use crate::db_manager::DBManager;
-use crate::trade_skill::TradeSkill;
-use crate::util::{convert_to_emoji, fill_embed, REACTIONS};
+use crate::error::{Error, Result};
+use crate::group_assignment::GroupAssignment;
+use crate::requirements::Requirements;
+use crate::roster_order::RosterOrder;
+use crate::roster_role::{RosterRole, ROSTER_ROLES};
+use crate::trade_skill::{TradeSkill, TRADE_SKILLS};
+use crate::war_message::WarMessage;
 use crate::weapon::{Weapon, WEAPONS};
-use crate::{util, DBHandler};
-use chrono::NaiveDateTime;
-use serenity::builder::CreateEmbed;
+use crate::{util, DBHandler, SignupModeKey};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serenity::builder::{CreateApplicationCommand, CreateApplicationCommandOption, CreateEmbed};
 use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
-use serenity::model::prelude::command::CommandOptionType;
-use serenity::model::prelude::interaction::application_command::CommandDataOptionValue;
+use serenity::model::prelude::command::{Command, CommandOptionType};
+use serenity::model::prelude::interaction::application_command::{
+    CommandDataOption, CommandDataOptionValue,
+};
 use serenity::model::prelude::{ChannelId, GuildId};
 use serenity::prelude::Context;
 use std::collections::HashMap;
+use std::env;
 use std::str::FromStr;
 use uuid::Uuid;
 
+pub fn create_war_command(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name("war")
+        .description("Manage war alerts")
+        .dm_permission(false)
+        .create_option(|o| {
+            o.name("alert")
+                .description("Post a war alert in this channel")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|s| {
+                    s.name("server")
+                        .description("The server the war takes place on")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+                .create_sub_option(|s| {
+                    s.name("faction")
+                        .description("The faction you are fighting for")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+                .create_sub_option(|s| {
+                    s.name("territory")
+                        .description("The territory in lowercase, with _ instead of spaces")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+                .create_sub_option(|s| {
+                    s.name("date")
+                        .description("The date of the war. Ex: 02/10/2022")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+                .create_sub_option(|s| {
+                    s.name("time")
+                        .description("The time of the war. Ex: 12:30pm")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                });
+            add_quota_options(o);
+            add_requirement_options(o)
+        })
+        .create_option(|o| {
+            o.name("event")
+                .description("Post a custom event, like an invasion or a dungeon night")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|s| {
+                    s.name("title")
+                        .description("The name of the event")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+                .create_sub_option(|s| {
+                    s.name("description")
+                        .description("What the event is about")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+                .create_sub_option(|s| {
+                    s.name("date")
+                        .description("The date of the event. Ex: 02/10/2022")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+                .create_sub_option(|s| {
+                    s.name("time")
+                        .description("The time of the event. Ex: 12:30pm")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                });
+            add_quota_options(o);
+            add_requirement_options(o)
+        })
+        .create_option(|o| {
+            o.name("edit")
+                .description("Change an alert while keeping its roster")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|s| {
+                    s.name("id")
+                        .description("The alert ID shown in the embed footer")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+                .create_sub_option(|s| {
+                    s.name("date")
+                        .description("The new date. Ex: 02/10/2022")
+                        .kind(CommandOptionType::String)
+                })
+                .create_sub_option(|s| {
+                    s.name("time")
+                        .description("The new time. Ex: 12:30pm")
+                        .kind(CommandOptionType::String)
+                })
+                .create_sub_option(|s| {
+                    s.name("territory")
+                        .description("The new territory in lowercase, with _ instead of spaces")
+                        .kind(CommandOptionType::String)
+                })
+                .create_sub_option(|s| {
+                    s.name("title")
+                        .description("The new title")
+                        .kind(CommandOptionType::String)
+                })
+        })
+        .create_option(|o| {
+            o.name("cancel")
+                .description("Cancel an alert and close its signups")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|s| {
+                    s.name("id")
+                        .description("The alert ID shown in the embed footer")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+                .create_sub_option(|s| {
+                    s.name("notify")
+                        .description("Send a DM to everyone who responded")
+                        .kind(CommandOptionType::Boolean)
+                })
+        })
+        .create_option(|o| {
+            o.name("sort")
+                .description("Choose how the members of each role are listed")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|s| {
+                    s.name("id")
+                        .description("The alert ID shown in the embed footer")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+                .create_sub_option(|s| {
+                    s.name("order")
+                        .description("The order to list members in")
+                        .kind(CommandOptionType::String)
+                        .add_string_choice(
+                            RosterOrder::Signup.get_label(),
+                            String::from(RosterOrder::Signup),
+                        )
+                        .add_string_choice(
+                            RosterOrder::GearScore.get_label(),
+                            String::from(RosterOrder::GearScore),
+                        )
+                        .required(true)
+                })
+        })
+        .create_option(|o| {
+            o.name("roster")
+                .description("Plan the army of an alert")
+                .kind(CommandOptionType::SubCommandGroup)
+                .create_sub_option(|s| {
+                    s.name("build")
+                        .description("Lay out the signups in groups of five and post the result")
+                        .kind(CommandOptionType::SubCommand)
+                        .create_sub_option(|i| {
+                            i.name("id")
+                                .description("The alert ID shown in the embed footer")
+                                .kind(CommandOptionType::String)
+                                .required(true)
+                        })
+                })
+                .create_sub_option(|s| {
+                    s.name("assign")
+                        .description("Pin a member into a group")
+                        .kind(CommandOptionType::SubCommand)
+                        .create_sub_option(|i| {
+                            i.name("id")
+                                .description("The alert ID shown in the embed footer")
+                                .kind(CommandOptionType::String)
+                                .required(true)
+                        })
+                        .create_sub_option(|i| {
+                            i.name("member")
+                                .description("The member to assign")
+                                .kind(CommandOptionType::User)
+                                .required(true)
+                        })
+                        .create_sub_option(|i| {
+                            i.name("group")
+                                .description("The group number")
+                                .kind(CommandOptionType::Integer)
+                                .min_int_value(1)
+                                .max_int_value(GROUP_COUNT)
+                                .required(true)
+                        })
+                })
+                .create_sub_option(|s| {
+                    s.name("move")
+                        .description("Move an assigned member to another group")
+                        .kind(CommandOptionType::SubCommand)
+                        .create_sub_option(|i| {
+                            i.name("id")
+                                .description("The alert ID shown in the embed footer")
+                                .kind(CommandOptionType::String)
+                                .required(true)
+                        })
+                        .create_sub_option(|i| {
+                            i.name("member")
+                                .description("The member to move")
+                                .kind(CommandOptionType::User)
+                                .required(true)
+                        })
+                        .create_sub_option(|i| {
+                            i.name("group")
+                                .description("The group number")
+                                .kind(CommandOptionType::Integer)
+                                .min_int_value(1)
+                                .max_int_value(GROUP_COUNT)
+                                .required(true)
+                        })
+                })
+                .create_sub_option(|s| {
+                    s.name("bench")
+                        .description("Put a member on standby")
+                        .kind(CommandOptionType::SubCommand)
+                        .create_sub_option(|i| {
+                            i.name("id")
+                                .description("The alert ID shown in the embed footer")
+                                .kind(CommandOptionType::String)
+                                .required(true)
+                        })
+                        .create_sub_option(|i| {
+                            i.name("member")
+                                .description("The member to bench")
+                                .kind(CommandOptionType::User)
+                                .required(true)
+                        })
+                })
+                .create_sub_option(|s| {
+                    s.name("add")
+                        .description("Sign up a member on their behalf")
+                        .kind(CommandOptionType::SubCommand)
+                        .create_sub_option(|i| {
+                            i.name("id")
+                                .description("The alert ID shown in the embed footer")
+                                .kind(CommandOptionType::String)
+                                .required(true)
+                        })
+                        .create_sub_option(|i| {
+                            i.name("member")
+                                .description("The member to sign up")
+                                .kind(CommandOptionType::User)
+                                .required(true)
+                        })
+                        .create_sub_option(|i| add_role_choices(i).required(true))
+                })
+                .create_sub_option(|s| {
+                    s.name("remove")
+                        .description("Remove a member from the roster and the waitlist")
+                        .kind(CommandOptionType::SubCommand)
+                        .create_sub_option(|i| {
+                            i.name("id")
+                                .description("The alert ID shown in the embed footer")
+                                .kind(CommandOptionType::String)
+                                .required(true)
+                        })
+                        .create_sub_option(|i| {
+                            i.name("member")
+                                .description("The member to remove")
+                                .kind(CommandOptionType::User)
+                                .required(true)
+                        })
+                })
+        })
+        .create_option(|o| {
+            o.name("mygroup")
+                .description("Show the army group you are in")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|s| {
+                    s.name("id")
+                        .description("The alert ID shown in the embed footer")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+        })
+        .create_option(|o| {
+            o.name("timezone")
+                .description("Set the time zone alert dates and times are entered in")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|s| {
+                    s.name("zone")
+                        .description("The time zone name. Ex: Europe/Berlin")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+        })
+        .create_option(|o| {
+            o.name("refresh")
+                .description("Rebuild every embed of an alert")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|s| {
+                    s.name("id")
+                        .description("The alert ID shown in the embed footer")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+        })
+        .create_option(|o| {
+            o.name("perm")
+                .description("Grant or revoke the bot admin permission")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|s| {
+                    s.name("option")
+                        .description("Whether to add or remove the permission")
+                        .kind(CommandOptionType::String)
+                        .add_string_choice("add", "add")
+                        .add_string_choice("remove", "remove")
+                        .required(true)
+                })
+                .create_sub_option(|s| {
+                    s.name("role")
+                        .description("The role to change")
+                        .kind(CommandOptionType::Role)
+                        .required(true)
+                })
+        })
+}
+
 pub async fn handle_war_command(
     ctx: &mut Context,
     command: &ApplicationCommandInteraction,
-) -> Option<&'static str> {
-    if let Some(sub_command) = command.data.options.get(0) {
+) -> Result<Option<&'static str>> {
+    Ok(if let Some(sub_command) = command.data.options.first() {
         if sub_command.kind == CommandOptionType::SubCommand {
             match sub_command.name.as_str() {
                 "alert" => {
@@ -31,73 +361,118 @@ pub async fn handle_war_command(
                                 .get::<DBHandler>()
                                 .expect("Failed to get db handler")
                                 .clone();
-                            if db_client.has_permission(guild_id.0, &member.roles).await {
-                                if let CommandDataOptionValue::String(server_str) = sub_command
-                                    .options
-                                    .get(0)
-                                    .expect("Failed to get date string")
-                                    .resolved
-                                    .as_ref()
-                                    .expect("Failed to resolve date string")
+                            if db_client.has_permission(guild_id.0, &member.roles).await? {
+                                let input = |name| {
+                                    get_named_string(sub_command, name)
+                                        .ok_or(Error::InvalidInput("Error resolving command input"))
+                                };
+                                let server_str = input("server")?;
+                                let faction_str = input("faction")?;
+                                let territory_str = input("territory")?;
+                                let date_str = input("date")?;
+                                let time_str = input("time")?;
+                                create_alert(
+                                    ctx,
+                                    &format!("{}@{}", date_str, time_str),
+                                    guild_id,
+                                    command.channel_id,
+                                    territory_str,
+                                    Some(server_str),
+                                    Some(faction_str),
+                                    db_client.as_ref(),
+                                    None,
+                                    None,
+                                    get_quotas(sub_command),
+                                    get_requirements(sub_command),
+                                )
+                                .await?;
+                                Some("Alert has been created.")
+                            } else {
+                                Some("You do not have permission to use this command.")
+                            }
+                        } else {
+                            Some("This command can only be used in guilds.")
+                        }
+                    } else {
+                        Some("This command can only be used in guilds.")
+                    }
+                }
+                "event" => {
+                    if let Some(guild_id) = command.guild_id {
+                        if let Some(member) = command.member.as_ref() {
+                            let db_client = ctx
+                                .data
+                                .read()
+                                .await
+                                .get::<DBHandler>()
+                                .expect("Failed to get db handler")
+                                .clone();
+                            if db_client.has_permission(guild_id.0, &member.roles).await? {
+                                let input = |name| {
+                                    get_named_string(sub_command, name)
+                                        .ok_or(Error::InvalidInput("Error resolving command input"))
+                                };
+                                let title = input("title")?;
+                                let description = input("description")?;
+                                let date_str = input("date")?;
+                                let time_str = input("time")?;
+                                create_alert(
+                                    ctx,
+                                    &format!("{}@{}", date_str, time_str),
+                                    guild_id,
+                                    command.channel_id,
+                                    "",
+                                    None,
+                                    None,
+                                    db_client.as_ref(),
+                                    Some(title),
+                                    Some(description),
+                                    get_quotas(sub_command),
+                                    get_requirements(sub_command),
+                                )
+                                .await?;
+                                Some("Event has been created.")
+                            } else {
+                                Some("You do not have permission to use this command.")
+                            }
+                        } else {
+                            Some("This command can only be used in guilds.")
+                        }
+                    } else {
+                        Some("This command can only be used in guilds.")
+                    }
+                }
+                "edit" => {
+                    if let Some(guild_id) = command.guild_id {
+                        if let Some(member) = command.member.as_ref() {
+                            let db_client = ctx
+                                .data
+                                .read()
+                                .await
+                                .get::<DBHandler>()
+                                .expect("Failed to get db handler")
+                                .clone();
+                            if db_client.has_permission(guild_id.0, &member.roles).await? {
+                                if let Some(CommandDataOptionValue::String(id)) =
+                                    get_named_option(sub_command, "id")
                                 {
-                                    if let CommandDataOptionValue::String(faction_str) = sub_command
-                                        .options
-                                        .get(1)
-                                        .expect("Failed to get faction string")
-                                        .resolved
-                                        .as_ref()
-                                        .expect("Failed to resolved faction string")
-                                    {
-                                        if let CommandDataOptionValue::String(territory_str) =
-                                            sub_command
-                                                .options
-                                                .get(2)
-                                                .expect("Failed to get territory string")
-                                                .resolved
-                                                .as_ref()
-                                                .expect("Failed to resolve territory string")
-                                        {
-                                            if let CommandDataOptionValue::String(date_str) =
-                                                sub_command
-                                                    .options
-                                                    .get(3)
-                                                    .expect("Failed to get date string")
-                                                    .resolved
-                                                    .as_ref()
-                                                    .expect("Failed to resolve date string")
-                                            {
-                                                if let CommandDataOptionValue::String(time_str) =
-                                                    sub_command
-                                                        .options
-                                                        .get(4)
-                                                        .expect("Failed to get time string")
-                                                        .resolved
-                                                        .as_ref()
-                                                        .expect("Failed to resolve time string")
-                                                {
-                                                    if let Err(str) = create_alert(
-                                                        ctx,
-                                                        &format!("{}@{}", date_str, time_str),
-                                                        guild_id,
-                                                        command.channel_id,
-                                                        territory_str,
-                                                        Some(server_str),
-                                                        Some(faction_str),
-                                                        &db_client,
-                                                        None,
-                                                    )
-                                                    .await
-                                                    {
-                                                        println!("Failed to create alert: {}", str);
-                                                    } else {
-                                                        return Some("Alert has been created.");
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
+                                    let uuid = Uuid::from_str(id)
+                                        .map_err(|_| Error::InvalidInput("Invalid alert ID"))?;
+                                    edit_alert(
+                                        ctx,
+                                        uuid,
+                                        guild_id,
+                                        get_named_string(sub_command, "date"),
+                                        get_named_string(sub_command, "time"),
+                                        get_named_string(sub_command, "territory"),
+                                        get_named_string(sub_command, "title"),
+                                        db_client.as_ref(),
+                                    )
+                                    .await?;
+                                    Some("The alert has been updated.")
+                                } else {
+                                    Some("Error resolving command input")
                                 }
-                                Some("Error resolving command input")
                             } else {
                                 Some("You do not have permission to use this command.")
                             }
@@ -108,7 +483,7 @@ pub async fn handle_war_command(
                         Some("This command can only be used in guilds.")
                     }
                 }
-                "refresh" => {
+                "cancel" => {
                     if let Some(guild_id) = command.guild_id {
                         if let Some(member) = command.member.as_ref() {
                             let db_client = ctx
@@ -118,18 +493,146 @@ pub async fn handle_war_command(
                                 .get::<DBHandler>()
                                 .expect("Failed to get db handler")
                                 .clone();
-                            if db_client.has_permission(guild_id.0, &member.roles).await {
-                                if let CommandDataOptionValue::String(str) = sub_command
-                                    .options
-                                    .get(0)
-                                    .unwrap()
-                                    .resolved
-                                    .as_ref()
-                                    .unwrap()
+                            if db_client.has_permission(guild_id.0, &member.roles).await? {
+                                if let Some(CommandDataOptionValue::String(id)) =
+                                    get_named_option(sub_command, "id")
                                 {
-                                    let uuid = Uuid::from_str(str).unwrap();
-                                    refresh_embeds(ctx, uuid, &db_client).await;
+                                    let uuid = Uuid::from_str(id)
+                                        .map_err(|_| Error::InvalidInput("Invalid alert ID"))?;
+                                    let notify = matches!(
+                                        get_named_option(sub_command, "notify"),
+                                        Some(CommandDataOptionValue::Boolean(true))
+                                    );
+                                    cancel_alert(ctx, uuid, guild_id, notify, db_client.as_ref())
+                                        .await?;
+                                    Some("The alert has been cancelled.")
+                                } else {
+                                    Some("Error resolving command input")
+                                }
+                            } else {
+                                Some("You do not have permission to use this command.")
+                            }
+                        } else {
+                            Some("This command can only be used in guilds.")
+                        }
+                    } else {
+                        Some("This command can only be used in guilds.")
+                    }
+                }
+                "sort" => {
+                    if let Some(guild_id) = command.guild_id {
+                        if let Some(member) = command.member.as_ref() {
+                            let db_client = ctx
+                                .data
+                                .read()
+                                .await
+                                .get::<DBHandler>()
+                                .expect("Failed to get db handler")
+                                .clone();
+                            if db_client.has_permission(guild_id.0, &member.roles).await? {
+                                if let (Some(id), Some(order)) = (
+                                    get_named_string(sub_command, "id"),
+                                    get_named_string(sub_command, "order"),
+                                ) {
+                                    let uuid = Uuid::from_str(id)
+                                        .map_err(|_| Error::InvalidInput("Invalid alert ID"))?;
+                                    let order = RosterOrder::try_from(order.to_string())
+                                        .map_err(Error::InvalidInput)?;
+                                    sort_roster(ctx, uuid, guild_id, order, db_client.as_ref())
+                                        .await?;
+                                    Some("The roster order has been changed.")
+                                } else {
+                                    Some("Error resolving command input")
+                                }
+                            } else {
+                                Some("You do not have permission to use this command.")
+                            }
+                        } else {
+                            Some("This command can only be used in guilds.")
+                        }
+                    } else {
+                        Some("This command can only be used in guilds.")
+                    }
+                }
+                "mygroup" => {
+                    if let Some(guild_id) = command.guild_id {
+                        let db_client = ctx
+                            .data
+                            .read()
+                            .await
+                            .get::<DBHandler>()
+                            .expect("Failed to get db handler")
+                            .clone();
+                        if let Some(id) = get_named_string(sub_command, "id") {
+                            let uuid = Uuid::from_str(id)
+                                .map_err(|_| Error::InvalidInput("Invalid alert ID"))?;
+                            let msg = describe_group(
+                                uuid,
+                                guild_id,
+                                command.user.id.0,
+                                db_client.as_ref(),
+                            )
+                            .await?;
+                            command
+                                .edit_original_interaction_response(&ctx.http, |r| r.content(msg))
+                                .await?;
+                            None
+                        } else {
+                            Some("Error resolving command input")
+                        }
+                    } else {
+                        Some("This command can only be used in guilds.")
+                    }
+                }
+                "timezone" => {
+                    if let Some(guild_id) = command.guild_id {
+                        if let Some(member) = command.member.as_ref() {
+                            let db_client = ctx
+                                .data
+                                .read()
+                                .await
+                                .get::<DBHandler>()
+                                .expect("Failed to get db handler")
+                                .clone();
+                            if db_client.has_permission(guild_id.0, &member.roles).await? {
+                                if let Some(zone) = get_named_string(sub_command, "zone") {
+                                    let time_zone = Tz::from_str(zone).map_err(|_| {
+                                        Error::InvalidInput(
+                                            "Unknown time zone. \
+                                            Use a name like Europe/Berlin or America/New_York.",
+                                        )
+                                    })?;
+                                    db_client.set_guild_time_zone(guild_id.0, time_zone).await?;
+                                    Some("The time zone for new alerts has been set.")
+                                } else {
+                                    Some("Error resolving command input")
                                 }
+                            } else {
+                                Some("You do not have permission to use this command.")
+                            }
+                        } else {
+                            Some("This command can only be used in guilds.")
+                        }
+                    } else {
+                        Some("This command can only be used in guilds.")
+                    }
+                }
+                "refresh" => {
+                    if let Some(guild_id) = command.guild_id {
+                        if let Some(member) = command.member.as_ref() {
+                            let db_client = ctx
+                                .data
+                                .read()
+                                .await
+                                .get::<DBHandler>()
+                                .expect("Failed to get db handler")
+                                .clone();
+                            if db_client.has_permission(guild_id.0, &member.roles).await? {
+                                let id = get_named_string(sub_command, "id")
+                                    .ok_or(Error::InvalidInput("Error resolving command input"))?;
+                                let uuid = Uuid::from_str(id)
+                                    .map_err(|_| Error::InvalidInput("Invalid alert ID"))?;
+                                refresh_embeds(ctx, uuid, db_client.as_ref()).await?;
                                 Some("All embeds with the ID specified have been refreshed.")
                             } else {
                                 Some("You do not have permission to use this command.")
@@ -153,33 +656,21 @@ pub async fn handle_war_command(
                                         .get::<DBHandler>()
                                         .expect("Failed to get db handler")
                                         .clone();
-                                    if let CommandDataOptionValue::String(option) = sub_command
-                                        .options
-                                        .get(0)
-                                        .unwrap()
-                                        .resolved
-                                        .as_ref()
-                                        .unwrap()
-                                    {
-                                        if let CommandDataOptionValue::Role(role) = sub_command
-                                            .options
-                                            .get(1)
-                                            .unwrap()
-                                            .resolved
-                                            .as_ref()
-                                            .unwrap()
+                                    if let Some(option) = get_named_string(sub_command, "option") {
+                                        if let Some(CommandDataOptionValue::Role(role)) =
+                                            get_named_option(sub_command, "role")
                                         {
-                                            match option.as_str() {
+                                            match option {
                                                 "add" => {
                                                     db_client
                                                         .add_permission(guild_id.0, role.id.0)
-                                                        .await;
+                                                        .await?;
                                                     Some("The bot admin permission has been added to the specified role.")
                                                 }
                                                 "remove" => {
                                                     db_client
                                                         .remove_permission(guild_id.0, role.id.0)
-                                                        .await;
+                                                        .await?;
                                                     Some("The bot admin permission has been removed from the specified role.")
                                                 }
                                                 _ => None,
@@ -205,19 +696,108 @@ pub async fn handle_war_command(
                 }
                 _ => Some("Invalid Command"),
             }
+        } else if sub_command.kind == CommandOptionType::SubCommandGroup
+            && sub_command.name == "roster"
+        {
+            handle_roster_command(ctx, command, sub_command).await?
         } else {
             Some("Invalid Option Type")
         }
     } else {
         Some("Invalid Subcommand")
-    }
+    })
+}
+
+pub fn create_register_command(
+    command: &mut CreateApplicationCommand,
+) -> &mut CreateApplicationCommand {
+    command
+        .name("register")
+        .description("Register your in-game data")
+        .create_option(|o| {
+            o.name("mainhand")
+                .description("Set your main hand weapon")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|s| add_weapon_choices(s).required(true))
+        })
+        .create_option(|o| {
+            o.name("secondary")
+                .description("Set your secondary weapon")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|s| add_weapon_choices(s).required(true))
+        })
+        .create_option(|o| {
+            o.name("level")
+                .description("Set your character level")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|s| {
+                    s.name("level")
+                        .description("Your character level")
+                        .kind(CommandOptionType::Integer)
+                        .min_int_value(1)
+                        .max_int_value(60)
+                        .required(true)
+                })
+        })
+        .create_option(|o| {
+            o.name("gearscore")
+                .description("Set your gear score")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|s| {
+                    s.name("gearscore")
+                        .description("Your gear score")
+                        .kind(CommandOptionType::Integer)
+                        .min_int_value(0)
+                        .max_int_value(625)
+                        .required(true)
+                })
+        })
+        .create_option(|o| {
+            o.name("tradeskill")
+                .description("Set the level of a trade skill")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|s| add_trade_skill_choices(s).required(true))
+                .create_sub_option(|s| {
+                    s.name("level")
+                        .description("The trade skill level")
+                        .kind(CommandOptionType::Integer)
+                        .min_int_value(0)
+                        .max_int_value(200)
+                        .required(true)
+                })
+        })
+        .create_option(|o| {
+            o.name("weaponlevel")
+                .description("Set the mastery level of a weapon")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|s| add_weapon_choices(s).required(true))
+                .create_sub_option(|s| {
+                    s.name("level")
+                        .description("The weapon mastery level")
+                        .kind(CommandOptionType::Integer)
+                        .min_int_value(0)
+                        .max_int_value(20)
+                        .required(true)
+                })
+        })
+        .create_option(|o| {
+            o.name("username")
+                .description("Set your in-game name")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|s| {
+                    s.name("username")
+                        .description("Your in-game name")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+        })
 }
 
 pub async fn handle_register_command(
     ctx: &mut Context,
     command: &ApplicationCommandInteraction,
-) -> Option<String> {
-    if let Some(sub_command) = command.data.options.get(0) {
+) -> Result<Option<String>> {
+    Ok(if let Some(sub_command) = command.data.options.first() {
         if sub_command.kind == CommandOptionType::SubCommand {
             let db_client = ctx
                 .data
@@ -230,15 +810,18 @@ pub async fn handle_register_command(
                 "mainhand" => {
                     if let CommandDataOptionValue::String(weapon_str) = sub_command
                         .options
-                        .get(0)
+                        .first()
                         .unwrap()
                         .resolved
                         .as_ref()
                         .unwrap()
                     {
-                        let weapon = Weapon::try_from(weapon_str.to_string()).unwrap();
-                        db_client.update_main_hand(command.user.id.0, weapon).await;
-                        update_all_embeds(ctx, command.user.id.0, &db_client).await;
+                        let weapon = Weapon::try_from(weapon_str.to_string())
+                            .map_err(Error::InvalidInput)?;
+                        db_client
+                            .update_main_hand(command.user.id.0, weapon)
+                            .await?;
+                        update_all_embeds(ctx, command.user.id.0, db_client.as_ref()).await?;
                         Some(format!("Main hand set to {}", weapon.get_label()))
                     } else {
                         Some("Invalid input for weapon".to_string())
@@ -247,15 +830,18 @@ pub async fn handle_register_command(
                 "secondary" => {
                     if let CommandDataOptionValue::String(weapon_str) = sub_command
                         .options
-                        .get(0)
+                        .first()
                         .unwrap()
                         .resolved
                         .as_ref()
                         .unwrap()
                     {
-                        let weapon = Weapon::try_from(weapon_str.to_string()).unwrap();
-                        db_client.update_secondary(command.user.id.0, weapon).await;
-                        update_all_embeds(ctx, command.user.id.0, &db_client).await;
+                        let weapon = Weapon::try_from(weapon_str.to_string())
+                            .map_err(Error::InvalidInput)?;
+                        db_client
+                            .update_secondary(command.user.id.0, weapon)
+                            .await?;
+                        update_all_embeds(ctx, command.user.id.0, db_client.as_ref()).await?;
                         Some(format!("Secondary set to {}", weapon.get_label()))
                     } else {
                         Some("Invalid input for weapon".to_string())
@@ -264,14 +850,16 @@ pub async fn handle_register_command(
                 "level" => {
                     if let &CommandDataOptionValue::Integer(level) = sub_command
                         .options
-                        .get(0)
+                        .first()
                         .unwrap()
                         .resolved
                         .as_ref()
                         .unwrap()
                     {
                         if (1..61).contains(&level) {
-                            db_client.update_level(command.user.id.0, level as u8).await;
+                            db_client
+                                .update_level(command.user.id.0, level as u8)
+                                .await?;
                             Some(format!("Level set to {}", level))
                         } else {
                             Some("Please enter a level from 1 to 60 (inclusive).".to_string())
@@ -283,7 +871,7 @@ pub async fn handle_register_command(
                 "gearscore" => {
                     if let &CommandDataOptionValue::Integer(gs) = sub_command
                         .options
-                        .get(0)
+                        .first()
                         .unwrap()
                         .resolved
                         .as_ref()
@@ -292,8 +880,8 @@ pub async fn handle_register_command(
                         if (0..626).contains(&gs) {
                             db_client
                                 .update_gear_score(command.user.id.0, gs as u16)
-                                .await;
-                            update_all_embeds(ctx, command.user.id.0, &db_client).await;
+                                .await?;
+                            update_all_embeds(ctx, command.user.id.0, db_client.as_ref()).await?;
                             Some(format!("Gear score set to {}", gs))
                         } else {
                             Some("Please enter a level from 0 to 625 (inclusive).".to_string())
@@ -305,7 +893,7 @@ pub async fn handle_register_command(
                 "tradeskill" => {
                     if let CommandDataOptionValue::String(skill) = sub_command
                         .options
-                        .get(0)
+                        .first()
                         .unwrap()
                         .resolved
                         .as_ref()
@@ -323,7 +911,7 @@ pub async fn handle_register_command(
                                 if (0..201).contains(&level) {
                                     db_client
                                         .update_trade_skill(command.user.id.0, level as u8, skill)
-                                        .await;
+                                        .await?;
                                     Some(format!("{} set to {}", skill.get_label(), level))
                                 } else {
                                     Some(
@@ -345,7 +933,7 @@ pub async fn handle_register_command(
                 "weaponlevel" => {
                     if let CommandDataOptionValue::String(weapon) = sub_command
                         .options
-                        .get(0)
+                        .first()
                         .unwrap()
                         .resolved
                         .as_ref()
@@ -363,8 +951,9 @@ pub async fn handle_register_command(
                                 if (0..21).contains(&level) {
                                     db_client
                                         .update_weapon_level(command.user.id.0, level as u8, weapon)
-                                        .await;
-                                    update_all_embeds(ctx, command.user.id.0, &db_client).await;
+                                        .await?;
+                                    update_all_embeds(ctx, command.user.id.0, db_client.as_ref())
+                                        .await?;
                                     Some(format!("{} set to {}", weapon.get_label(), level))
                                 } else {
                                     Some(
@@ -385,14 +974,16 @@ pub async fn handle_register_command(
                 "username" => {
                     if let CommandDataOptionValue::String(username) = sub_command
                         .options
-                        .get(0)
+                        .first()
                         .unwrap()
                         .resolved
                         .as_ref()
                         .unwrap()
                     {
-                        db_client.update_username(command.user.id.0, username).await;
-                        update_all_embeds(ctx, command.user.id.0, &db_client).await;
+                        db_client
+                            .update_username(command.user.id.0, username)
+                            .await?;
+                        update_all_embeds(ctx, command.user.id.0, db_client.as_ref()).await?;
                         Some(format!("Username set to {}", username))
                     } else {
                         Some("Invalid input for username".to_string())
@@ -405,37 +996,113 @@ pub async fn handle_register_command(
         }
     } else {
         Some("Invalid Subcommand".to_string())
-    }
+    })
+}
+
+pub fn create_war_stat_command(
+    command: &mut CreateApplicationCommand,
+) -> &mut CreateApplicationCommand {
+    command
+        .name("warstats")
+        .description("Statistics about registered players")
+        .create_option(|o| {
+            o.name("summary")
+                .description("Show average level, gear score and weapon counts")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|s| {
+                    s.name("locale")
+                        .description("Include everyone or only this server")
+                        .kind(CommandOptionType::String)
+                        .add_string_choice("global", "global")
+                        .add_string_choice("local", "local")
+                        .required(true)
+                })
+        })
+        .create_option(|o| {
+            o.name("search")
+                .description("Find players on this server")
+                .kind(CommandOptionType::SubCommandGroup)
+                .create_sub_option(|s| {
+                    s.name("tradeskill")
+                        .description("Find players by trade skill level")
+                        .kind(CommandOptionType::SubCommand)
+                        .create_sub_option(|s| add_trade_skill_choices(s).required(true))
+                        .create_sub_option(|s| {
+                            s.name("level")
+                                .description("The highest level to include")
+                                .kind(CommandOptionType::Integer)
+                                .min_int_value(0)
+                                .max_int_value(200)
+                        })
+                })
+                .create_sub_option(|s| {
+                    s.name("weapon")
+                        .description("Find players by weapon mastery level")
+                        .kind(CommandOptionType::SubCommand)
+                        .create_sub_option(|s| add_weapon_choices(s).required(true))
+                        .create_sub_option(|s| {
+                            s.name("level")
+                                .description("The highest level to include")
+                                .kind(CommandOptionType::Integer)
+                                .min_int_value(0)
+                                .max_int_value(20)
+                        })
+                })
+                .create_sub_option(|s| {
+                    s.name("gearscore")
+                        .description("Find players by gear score")
+                        .kind(CommandOptionType::SubCommand)
+                        .create_sub_option(|s| {
+                            s.name("gearscore")
+                                .description("The highest gear score to include")
+                                .kind(CommandOptionType::Integer)
+                                .min_int_value(0)
+                                .max_int_value(625)
+                        })
+                })
+                .create_sub_option(|s| {
+                    s.name("level")
+                        .description("Find players by character level")
+                        .kind(CommandOptionType::SubCommand)
+                        .create_sub_option(|s| {
+                            s.name("level")
+                                .description("The highest level to include")
+                                .kind(CommandOptionType::Integer)
+                                .min_int_value(1)
+                                .max_int_value(60)
+                        })
+                })
+        })
 }
 
 pub async fn handle_war_stat_command(
     ctx: &mut Context,
     command: &ApplicationCommandInteraction,
-) -> Result<CreateEmbed, &'static str> {
-    if let Some(sub_command) = command.data.options.get(0) {
+) -> Result<CreateEmbed> {
+    if let Some(sub_command) = command.data.options.first() {
         if sub_command.kind == CommandOptionType::SubCommand {
             if sub_command.name.as_str() == "summary" {
-                if let Some(locale) = sub_command.options.get(0).unwrap().resolved.as_ref() {
+                if let Some(locale) = sub_command.options.first().unwrap().resolved.as_ref() {
                     if let CommandDataOptionValue::String(locale) = locale {
                         let local = locale == "local";
                         if local && command.guild_id.is_none() {
-                            Err("The local option can only be called within a guild.")
+                            Err(Error::InvalidInput(
+                                "The local option can only be called within a guild.",
+                            ))
                         } else {
                             let db_client =
                                 ctx.data.read().await.get::<DBHandler>().unwrap().clone();
-                            Ok(
-                                generate_stats_embed(ctx, local, command.guild_id, &db_client)
-                                    .await,
-                            )
+                            generate_stats_embed(ctx, local, command.guild_id, db_client.as_ref())
+                                .await
                         }
                     } else {
-                        Err("Invalid option type")
+                        Err(Error::InvalidInput("Invalid option type"))
                     }
                 } else {
-                    Err("No locale option provided")
+                    Err(Error::InvalidInput("No locale option provided"))
                 }
             } else {
-                Err("Invalid command option")
+                Err(Error::InvalidInput("Invalid command option"))
             }
         } else if sub_command.kind == CommandOptionType::SubCommandGroup {
             if sub_command.name.as_str() == "search" {
@@ -445,7 +1112,7 @@ pub async fn handle_war_stat_command(
                         match option.name.as_str() {
                             "tradeskill" => {
                                 let trade_skill_str =
-                                    option.options.get(0).unwrap().resolved.as_ref().unwrap();
+                                    option.options.first().unwrap().resolved.as_ref().unwrap();
                                 if let CommandDataOptionValue::String(trade_skill_str) =
                                     trade_skill_str
                                 {
@@ -471,24 +1138,24 @@ pub async fn handle_war_stat_command(
                                             .get::<DBHandler>()
                                             .expect("Failed to get DB Client")
                                             .clone();
-                                        Ok(search_trade_skills(
+                                        search_trade_skills(
                                             ctx,
                                             guild_id,
                                             trade_skill,
                                             level,
-                                            &db_client,
+                                            db_client.as_ref(),
                                         )
-                                        .await)
+                                        .await
                                     } else {
-                                        Err("Invalid TradeSkill")
+                                        Err(Error::InvalidInput("Invalid TradeSkill"))
                                     }
                                 } else {
-                                    Err("No TradeSkill Provided")
+                                    Err(Error::InvalidInput("No TradeSkill Provided"))
                                 }
                             }
                             "weapon" => {
                                 let weapon_str =
-                                    option.options.get(0).unwrap().resolved.as_ref().unwrap();
+                                    option.options.first().unwrap().resolved.as_ref().unwrap();
                                 if let CommandDataOptionValue::String(weapon_str) = weapon_str {
                                     if let Ok(weapon) = Weapon::try_from(weapon_str.to_string()) {
                                         let level = if let Some(level_data) = option.options.get(1)
@@ -510,19 +1177,23 @@ pub async fn handle_war_stat_command(
                                             .get::<DBHandler>()
                                             .expect("Failed to get DB Client")
                                             .clone();
-                                        Ok(search_weapon_levels(
-                                            ctx, guild_id, weapon, level, &db_client,
+                                        search_weapon_levels(
+                                            ctx,
+                                            guild_id,
+                                            weapon,
+                                            level,
+                                            db_client.as_ref(),
                                         )
-                                        .await)
+                                        .await
                                     } else {
-                                        Err("Invalid Weapon")
+                                        Err(Error::InvalidInput("Invalid Weapon"))
                                     }
                                 } else {
-                                    Err("No Weapon Provided")
+                                    Err(Error::InvalidInput("No Weapon Provided"))
                                 }
                             }
                             "gearscore" => {
-                                let gs = if let Some(gs) = option.options.get(0) {
+                                let gs = if let Some(gs) = option.options.first() {
                                     if let CommandDataOptionValue::Integer(gs) =
                                         gs.resolved.as_ref().unwrap()
                                     {
@@ -540,10 +1211,10 @@ pub async fn handle_war_stat_command(
                                     .get::<DBHandler>()
                                     .expect("Failed to get DB Client")
                                     .clone();
-                                Ok(search_gear_score(ctx, guild_id, gs, &db_client).await)
+                                search_gear_score(ctx, guild_id, gs, db_client.as_ref()).await
                             }
                             "level" => {
-                                let level = if let Some(level) = option.options.get(0) {
+                                let level = if let Some(level) = option.options.first() {
                                     if let CommandDataOptionValue::Integer(level) =
                                         level.resolved.as_ref().unwrap()
                                     {
@@ -561,31 +1232,422 @@ pub async fn handle_war_stat_command(
                                     .get::<DBHandler>()
                                     .expect("Failed to get DB Client")
                                     .clone();
-                                Ok(search_level(ctx, guild_id, level, &db_client).await)
+                                search_level(ctx, guild_id, level, db_client.as_ref()).await
                             }
-                            _ => Err("This command is currently under maintenance."),
+                            _ => Err(Error::InvalidInput(
+                                "This command is currently under maintenance.",
+                            )),
                         }
                     } else {
-                        Err("Invalid subcommand option")
+                        Err(Error::InvalidInput("Invalid subcommand option"))
                     }
                 } else {
-                    Err("This command can only be used within guilds")
+                    Err(Error::InvalidInput(
+                        "This command can only be used within guilds",
+                    ))
                 }
             } else {
-                Err("Invalid subcommand")
+                Err(Error::InvalidInput("Invalid subcommand"))
             }
         } else {
-            Err("Invalid option type")
+            Err(Error::InvalidInput("Invalid option type"))
+        }
+    } else {
+        Err(Error::InvalidInput("No sub command supplied"))
+    }
+}
+
+/// Handles the `/war roster` sub commands. All of them need the bot permission.
+async fn handle_roster_command(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    group: &CommandDataOption,
+) -> Result<Option<&'static str>> {
+    let (guild_id, member) = match (command.guild_id, command.member.as_ref()) {
+        (Some(guild_id), Some(member)) => (guild_id, member),
+        _ => return Ok(Some("This command can only be used in guilds.")),
+    };
+    let db_client = ctx
+        .data
+        .read()
+        .await
+        .get::<DBHandler>()
+        .expect("Failed to get db handler")
+        .clone();
+    if !db_client.has_permission(guild_id.0, &member.roles).await? {
+        return Ok(Some("You do not have permission to use this command."));
+    }
+
+    Ok(if let Some(sub_command) = group.options.first() {
+        match sub_command.name.as_str() {
+            "build" => {
+                if let Some(CommandDataOptionValue::String(id)) =
+                    get_named_option(sub_command, "id")
+                {
+                    let uuid =
+                        Uuid::from_str(id).map_err(|_| Error::InvalidInput("Invalid alert ID"))?;
+                    build_army(ctx, uuid, guild_id, command.channel_id, db_client.as_ref()).await?;
+                    Some("The army layout has been posted.")
+                } else {
+                    Some("Error resolving command input")
+                }
+            }
+            "assign" | "move" | "bench" => {
+                if let (
+                    Some(CommandDataOptionValue::String(id)),
+                    Some(CommandDataOptionValue::User(user, _)),
+                ) = (
+                    get_named_option(sub_command, "id"),
+                    get_named_option(sub_command, "member"),
+                ) {
+                    let uuid =
+                        Uuid::from_str(id).map_err(|_| Error::InvalidInput("Invalid alert ID"))?;
+                    let group = if let Some(CommandDataOptionValue::Integer(group)) =
+                        get_named_option(sub_command, "group")
+                    {
+                        Some(*group as u8)
+                    } else {
+                        None
+                    };
+                    let expect_assigned = match sub_command.name.as_str() {
+                        "assign" => Some(false),
+                        "move" => Some(true),
+                        _ => None,
+                    };
+                    assign_group(
+                        ctx,
+                        uuid,
+                        guild_id,
+                        user.id.0,
+                        group,
+                        expect_assigned,
+                        db_client.as_ref(),
+                    )
+                    .await?;
+                    Some(if group.is_some() {
+                        "The member has been assigned."
+                    } else {
+                        "The member has been put on standby."
+                    })
+                } else {
+                    Some("Error resolving command input")
+                }
+            }
+            "add" => {
+                if let (
+                    Some(CommandDataOptionValue::String(id)),
+                    Some(CommandDataOptionValue::User(user, _)),
+                    Some(role),
+                ) = (
+                    get_named_option(sub_command, "id"),
+                    get_named_option(sub_command, "member"),
+                    get_named_string(sub_command, "role").and_then(RosterRole::from_field),
+                ) {
+                    let uuid =
+                        Uuid::from_str(id).map_err(|_| Error::InvalidInput("Invalid alert ID"))?;
+                    Some(
+                        if proxy_signup(
+                            ctx,
+                            uuid,
+                            guild_id,
+                            user.id.0,
+                            role,
+                            member.user.id.0,
+                            db_client.as_ref(),
+                        )
+                        .await?
+                        {
+                            "The member has been put on the waitlist."
+                        } else {
+                            "The member has been signed up."
+                        },
+                    )
+                } else {
+                    Some("Error resolving command input")
+                }
+            }
+            "remove" => {
+                if let (
+                    Some(CommandDataOptionValue::String(id)),
+                    Some(CommandDataOptionValue::User(user, _)),
+                ) = (
+                    get_named_option(sub_command, "id"),
+                    get_named_option(sub_command, "member"),
+                ) {
+                    let uuid =
+                        Uuid::from_str(id).map_err(|_| Error::InvalidInput("Invalid alert ID"))?;
+                    proxy_remove(ctx, uuid, guild_id, user.id.0, db_client.as_ref()).await?;
+                    Some("The member has been removed.")
+                } else {
+                    Some("Error resolving command input")
+                }
+            }
+            _ => Some("Invalid Command"),
         }
     } else {
-        Err("No sub command supplied")
+        Some("Invalid Subcommand")
+    })
+}
+
+/// Lays out the roster of an alert in groups and posts it in `channel_id`. Building it again
+/// updates the layout already posted in that channel.
+async fn build_army(
+    ctx: &Context,
+    uuid: Uuid,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<()> {
+    let ac = get_guild_alert_connector(uuid, guild_id, db_client).await?;
+    let army = util::build_army(&ac, db_client).await?;
+    let embed = util::build_army_embed(&ac, &army, db_client).await?;
+
+    if let Some(army_message) = ac.army_messages.iter().find(|army_message| {
+        army_message.get_guild_id() == guild_id.0 && army_message.get_channel_id() == channel_id.0
+    }) {
+        if channel_id
+            .edit_message(ctx, army_message.get_message_id(), |m| {
+                m.set_embed(embed.clone())
+            })
+            .await
+            .is_ok()
+        {
+            return Ok(());
+        }
     }
+    let message = channel_id.send_message(ctx, |m| m.set_embed(embed)).await?;
+    db_client
+        .set_army_message(
+            uuid,
+            WarMessage::new(guild_id.0, channel_id.0, message.id.0),
+        )
+        .await
 }
 
-async fn update_all_embeds(ctx: &Context, user_id: u64, db_client: &mongodb::Client) {
-    for ac in db_client.get_alert_connectors_with_user_id(user_id).await {
-        util::update_embeds(Uuid::from_str(&ac.code).unwrap(), ctx, db_client).await;
+/// Pins a member of the combat roster into `group`, or onto the bench for `None`. With
+/// `expect_assigned` set, the member must already be in a group, or must not be, respectively.
+async fn assign_group(
+    ctx: &Context,
+    uuid: Uuid,
+    guild_id: GuildId,
+    user_id: u64,
+    group: Option<u8>,
+    expect_assigned: Option<bool>,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<()> {
+    let ac = get_guild_alert_connector(uuid, guild_id, db_client).await?;
+    if !ac.has_combat_role(user_id) {
+        return Err(Error::InvalidInput(
+            "This member is not signed up for a combat role on this alert.",
+        ));
     }
+
+    let current = ac
+        .get_assignment(user_id)
+        .and_then(|assignment| assignment.group);
+    match (expect_assigned, current) {
+        (Some(true), None) => {
+            return Err(Error::InvalidInput(
+                "This member is not assigned to a group yet. Use /war roster assign instead.",
+            ))
+        }
+        (Some(false), Some(_)) => {
+            return Err(Error::InvalidInput(
+                "This member is already assigned to a group. Use /war roster move instead.",
+            ))
+        }
+        _ => {}
+    }
+    if group.is_some_and(|group| {
+        current != Some(group)
+            && ac
+                .get_assigned(Some(group))
+                .into_iter()
+                .filter(|&id| ac.has_combat_role(id))
+                .count()
+                >= GROUP_SIZE
+    }) {
+        return Err(Error::InvalidInput(
+            "This group already has five members assigned.",
+        ));
+    }
+
+    db_client
+        .set_group_assignment(uuid, GroupAssignment::new(user_id, group))
+        .await?;
+    util::update_embeds(uuid, ctx, db_client).await?;
+    util::update_army_embeds(uuid, ctx, db_client).await
+}
+
+/// Signs up a member on behalf of the officer `added_by` and lets the member know. Officers
+/// can sign up members that don't meet the alert requirements. Returns whether the member was
+/// put on the waitlist.
+async fn proxy_signup(
+    ctx: &Context,
+    uuid: Uuid,
+    guild_id: GuildId,
+    user_id: u64,
+    role: RosterRole,
+    added_by: u64,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<bool> {
+    let ac = get_guild_alert_connector(uuid, guild_id, db_client).await?;
+    if !ac.is_open() {
+        return Err(Error::InvalidInput("This alert no longer accepts signups."));
+    }
+
+    let change = db_client
+        .signup(uuid, user_id, role, Some(added_by))
+        .await?;
+    if let Some(previous) = change.previous {
+        util::remove_user_reaction(ctx, &ac, user_id, previous).await;
+    }
+    util::notify_promotions(ctx, &ac, &change.promoted).await;
+    util::notify_user(
+        ctx,
+        user_id,
+        &if let Some(position) = change.waitlisted {
+            format!(
+                "<@{}> signed you up as {} for **{}** ({}). The role is full, \
+                you are #{} on its waitlist.",
+                added_by,
+                role.get_name(),
+                ac.get_title(),
+                ac.get_start_label(),
+                position
+            )
+        } else {
+            format!(
+                "<@{}> signed you up as {} for **{}** ({}).",
+                added_by,
+                role.get_name(),
+                ac.get_title(),
+                ac.get_start_label()
+            )
+        },
+    )
+    .await;
+    util::prompt_registration(ctx, user_id, db_client).await?;
+    util::update_embeds(uuid, ctx, db_client).await?;
+    Ok(change.waitlisted.is_some())
+}
+
+/// Removes a member from their role and the waitlist of an alert and lets them know.
+async fn proxy_remove(
+    ctx: &Context,
+    uuid: Uuid,
+    guild_id: GuildId,
+    user_id: u64,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<()> {
+    let ac = get_guild_alert_connector(uuid, guild_id, db_client).await?;
+    let role = ac.get_user_role(user_id);
+    let waiting_for = ac.get_waitlist_position(user_id).map(|(role, _)| role);
+    if role.is_none() && waiting_for.is_none() {
+        return Err(Error::InvalidInput(
+            "This member is not signed up for this alert.",
+        ));
+    }
+
+    for role in role.iter().chain(waiting_for.iter()) {
+        let change = db_client.withdraw(uuid, user_id, *role).await?;
+        util::remove_user_reaction(ctx, &ac, user_id, *role).await;
+        util::notify_promotions(ctx, &ac, &change.promoted).await;
+    }
+    util::notify_user(
+        ctx,
+        user_id,
+        &format!(
+            "An officer removed you from **{}** ({}).",
+            ac.get_title(),
+            ac.get_start_label()
+        ),
+    )
+    .await;
+    util::update_embeds(uuid, ctx, db_client).await
+}
+
+/// Tells the user which army group they are in and who else is in it.
+async fn describe_group(
+    uuid: Uuid,
+    guild_id: GuildId,
+    user_id: u64,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<String> {
+    let ac = get_guild_alert_connector(uuid, guild_id, db_client).await?;
+    if !ac.has_combat_role(user_id) {
+        return Ok("You are not signed up for a combat role on this alert.".to_string());
+    }
+
+    let army = util::build_army(&ac, db_client).await?;
+    Ok(if let Some(group) = army.get_group(user_id) {
+        let mut names = vec![];
+        for &member in army.groups[group - 1].iter().filter(|&&id| id != user_id) {
+            names.push(util::get_display_name(member, db_client).await?);
+        }
+        if names.is_empty() {
+            format!("You are in group {} of **{}**.", group, ac.get_title())
+        } else {
+            format!(
+                "You are in group {} of **{}** with {}.",
+                group,
+                ac.get_title(),
+                names.join(", ")
+            )
+        }
+    } else {
+        format!("You are on standby for **{}**.", ac.get_title())
+    })
+}
+
+/// The alert with the ID, if it was posted on the guild.
+async fn get_guild_alert_connector(
+    uuid: Uuid,
+    guild_id: GuildId,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<AlertConnector> {
+    db_client
+        .get_alert_connector(uuid)
+        .await?
+        .filter(|ac| ac.get_guild_ids().contains(&guild_id.0))
+        .ok_or(Error::InvalidInput(
+            "No alert with this ID was posted on this server.",
+        ))
+}
+
+async fn update_all_embeds(
+    ctx: &Context,
+    user_id: u64,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<()> {
+    for ac in db_client
+        .get_upcoming_alert_connectors_with_user_id(user_id, Utc::now().timestamp())
+        .await?
+    {
+        if let Ok(uuid) = Uuid::from_str(&ac.code) {
+            util::update_embeds(uuid, ctx, db_client).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Parses the `date@time` string built from the date and time command options as a local time
+/// in `time_zone`.
+fn parse_date_time(date_time: &str, time_zone: Tz) -> Result<DateTime<Tz>> {
+    let date_time = NaiveDateTime::parse_from_str(&date_time.to_lowercase(), "%m/%e/%Y@%I:%M%P")
+        .map_err(|_| {
+            Error::InvalidInput(
+                "The date or time entered was invalid. \
+                Please use the formats mm/dd/YYYY and HH:MMP respectively. Ex: 02/10/2022 and 12:30pm",
+            )
+        })?;
+    time_zone
+        .from_local_datetime(&date_time)
+        .single()
+        .ok_or(Error::InvalidInput(
+            "This time does not exist or is ambiguous in the server's time zone because of a \
+            daylight saving change.",
+        ))
 }
 
 async fn create_alert(
@@ -596,136 +1658,296 @@ async fn create_alert(
     territory: &str,
     server: Option<&str>,
     faction: Option<&str>,
-    db_client: &mongodb::Client,
+    db_client: &(impl DBManager + ?Sized),
     name: Option<&str>,
-) -> Result<(), &'static str> {
-    let date_time = NaiveDateTime::parse_from_str(&date_time.to_lowercase(), "%m/%e/%Y@%I:%M%P")
-        .map_err(|_| {
-            "The date or time entered was invalid. \
-            Please use the formats mm/dd/YYYY and HH:MMP respectively. Ex: 02/10/2022 and 12:30pm"
-        })?;
+    description: Option<&str>,
+    quotas: HashMap<RosterRole, u32>,
+    requirements: Requirements,
+) -> Result<()> {
+    let time_zone = db_client.get_guild_settings(guild.0).await?.get_time_zone();
+    let date_time = parse_date_time(date_time, time_zone)?;
 
     let server = if let Some(server) = server {
         server.to_string()
     } else {
         format!("localevent{}", guild.0)
     };
-    let faction = if let Some(faction) = faction {
-        faction
-    } else {
-        "event"
-    };
+    let faction = faction.unwrap_or("event");
     let date_str = date_time.format("%a %e. %b").to_string();
     let time_str = date_time.format("%H:%M%P").to_string();
 
     let uuid = Uuid::new_v5(
         &Uuid::NAMESPACE_OID,
         format!(
-            "{}{}{}{}{}",
+            "{}{}{}{}{}{}",
             date_str,
             time_str,
             server.to_lowercase(),
             faction.to_lowercase(),
-            territory.to_lowercase()
+            territory.to_lowercase(),
+            name.unwrap_or_default().to_lowercase()
         )
         .as_bytes(),
     );
 
+    // The ID comes from the details, so posting the same war again finds the stored alert.
+    let mut existing = db_client.get_alert_connector(uuid).await?;
+    if let Some(ac) = existing.as_mut() {
+        match ac.status {
+            AlertStatus::Open => {}
+            AlertStatus::Cancelled
+                if ac
+                    .starts_at
+                    .is_some_and(|starts_at| starts_at > Utc::now().timestamp()) =>
+            {
+                // Posting a cancelled war again that has yet to start brings it back.
+                db_client.set_alert_status(uuid, AlertStatus::Open).await?;
+                ac.status = AlertStatus::Open;
+                util::update_embeds(uuid, ctx, db_client).await?;
+            }
+            AlertStatus::Closed => {
+                return Err(Error::InvalidInput(
+                    "Signups for this war are already closed.",
+                ))
+            }
+            _ => {
+                return Err(Error::InvalidInput(
+                    "This war is already over. Pick another date or time for a new one.",
+                ))
+            }
+        }
+    }
+
     if !db_client
         .channel_contains_war_message(guild.0, channel_id.0, uuid)
-        .await
+        .await?
     {
-        let mut embed = CreateEmbed::default()
-            .title(name.unwrap_or("War Alert"))
-            .description(convert_to_emoji(territory))
-            .field(format!(":calendar_spiral: {}", date_str), "\u{200B}", true)
-            .field("\u{200B}", "\u{200B}", true)
-            .field(format!(":clock1: {}", time_str), "\u{200B}", true)
-            .to_owned();
-
-        if let Some(ac) = db_client.get_alert_connector(uuid).await {
-            fill_embed(&mut embed, &ac, db_client).await;
-        } else {
-            embed = embed
-                .field(":shield: TANK :shield:", "\u{200B}", true)
-                .field("\u{200B}", "\u{200B}", true)
-                .field(":dagger: MDPS :dagger:", "\u{200B}", true)
-                .field("\u{200B}", "\u{200B}", false)
-                .field(":archery: Physical RDPS :archery:", "\u{200B}", true)
-                .field("\u{200B}", "\u{200B}", true)
-                .field(":magic_wand: Elemental RDPS :magic_wand:", "\u{200B}", true)
-                .field("\u{200B}", "\u{200B}", false)
-                .field(":heart: Healer :heart:", "\u{200B}", true)
-                .field("\u{200B}", "\u{200B}", true)
-                .field(":boom: Artillery :boom:", "\u{200B}", true)
-                .field("\u{200B}", "\u{200B}", false)
-                .field(":question: Tentative :question:", "\u{200B}", true)
-                .field("\u{200B}", "\u{200B}", true)
-                .field(":no_entry: Not Available :no_entry:", "\u{200B}", true)
-                .field("\u{200B}", "\u{200B}", false)
-                .to_owned();
-        }
-        embed = embed
-            .field(
-                "NOTE",
-                "Remember to use '/register' to register your in-game data.",
-                false,
-            )
-            .footer(|f| f.text(uuid.hyphenated().to_string()))
-            .to_owned();
+        // Quotas and requirements are set when the alert is created. Posting it to more channels
+        // keeps them.
+        let is_new = existing.is_none();
+        let mut ac = existing.unwrap_or_else(|| AlertConnector {
+            code: uuid.to_string(),
+            date: date_str,
+            time: time_str,
+            server,
+            faction: faction.to_string(),
+            territory: territory.to_string(),
+            title: name.unwrap_or_default().to_string(),
+            description: description.unwrap_or_default().to_string(),
+            r#type: u8::from(name.is_some()),
+            starts_at: Some(date_time.timestamp()),
+            quotas,
+            requirements,
+            ..Default::default()
+        });
+        let embed = util::build_embed(&ac, db_client).await?;
+
+        let signup_mode = ctx
+            .data
+            .read()
+            .await
+            .get::<SignupModeKey>()
+            .copied()
+            .unwrap_or_default();
 
-        let result = channel_id
+        // The signup options are only added once the alert is stored, so no signup can arrive
+        // before it exists.
+        let mut message = channel_id
             .send_message(&ctx, |m| {
                 m.embed(|e| {
                     *e = embed;
                     e
                 })
             })
-            .await;
-        if let Err(why) = result.as_ref() {
-            println!("Failed to send message: {}", why);
-        } else if let Ok(message) = result {
-            for reaction in REACTIONS {
+            .await?;
+
+        let war_message = WarMessage::new(guild.0, channel_id.0, message.id.0);
+        if is_new {
+            ac.war_messages.push(war_message);
+            db_client.create_alert_connector(&ac).await?;
+        } else {
+            db_client.add_war_message(uuid, war_message).await?;
+        }
+
+        // Only open alerts get signup controls, others would just refuse every signup.
+        if ac.is_open() {
+            if signup_mode == SignupMode::Buttons {
                 message
-                    .react(&ctx, reaction)
-                    .await
-                    .expect("Failed to react to embed");
+                    .edit(&ctx, |m| m.components(add_signup_components))
+                    .await?;
+            } else {
+                for role in ROSTER_ROLES {
+                    message.react(&ctx, role.get_emoji()).await?;
+                }
             }
+        }
+    }
+
+    Ok(())
+}
+
+/// Changes the given details of an alert, keeping its ID and roster, and tells everyone who
+/// responded to it what changed.
+async fn edit_alert(
+    ctx: &Context,
+    uuid: Uuid,
+    guild_id: GuildId,
+    date: Option<&str>,
+    time: Option<&str>,
+    territory: Option<&str>,
+    title: Option<&str>,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<()> {
+    let mut ac = db_client
+        .get_alert_connector(uuid)
+        .await?
+        .filter(|ac| ac.get_guild_ids().contains(&guild_id.0))
+        .ok_or(Error::InvalidInput(
+            "No alert with this ID was posted on this server.",
+        ))?;
+    if ac.status == AlertStatus::Cancelled {
+        return Err(Error::InvalidInput(
+            "This alert was cancelled and can no longer be edited.",
+        ));
+    }
 
-            db_client
-                .add_war_message(
-                    guild.0,
-                    channel_id.0,
-                    message.id.0,
-                    uuid,
-                    &date_str,
-                    &time_str,
-                    &server,
-                    faction,
-                    territory,
-                    if let Some(name) = name { name } else { "" },
-                    u8::from(name.is_some()),
-                )
-                .await;
+    let mut changes = vec![];
+    if date.is_some() || time.is_some() {
+        let time_zone = db_client
+            .get_guild_settings(guild_id.0)
+            .await?
+            .get_time_zone();
+        let starts_at = ac
+            .starts_at
+            .and_then(|starts_at| time_zone.timestamp_opt(starts_at, 0).single());
+        let date = date
+            .map(str::to_string)
+            .or_else(|| starts_at.map(|s| s.format("%m/%d/%Y").to_string()));
+        let time = time
+            .map(str::to_string)
+            .or_else(|| starts_at.map(|s| s.format("%I:%M%P").to_string()));
+        if let (Some(date), Some(time)) = (date, time) {
+            let date_time = parse_date_time(&format!("{}@{}", date, time), time_zone)?;
+            ac.date = date_time.format("%a %e. %b").to_string();
+            ac.time = date_time.format("%H:%M%P").to_string();
+            ac.starts_at = Some(date_time.timestamp());
+            changes.push(format!("It now starts {}.", ac.get_start_label()));
+        } else {
+            return Err(Error::InvalidInput(
+                "This alert has no stored start time. Please provide both the date and the time.",
+            ));
         }
     }
+    if let Some(territory) = territory {
+        ac.territory = territory.to_string();
+        changes.push(format!("The territory is now {}.", territory));
+    }
+    if let Some(title) = title {
+        ac.title = title.to_string();
+        changes.push(format!("The title is now {}.", title));
+    }
+    if changes.is_empty() {
+        return Err(Error::InvalidInput(
+            "Please provide at least one detail to change.",
+        ));
+    }
+
+    db_client.update_alert_details(&ac).await?;
+    // Moving a closed or finished war into the future opens its signups again.
+    if matches!(ac.status, AlertStatus::Closed | AlertStatus::Archived)
+        && ac
+            .starts_at
+            .is_some_and(|starts_at| starts_at > Utc::now().timestamp())
+    {
+        db_client.set_alert_status(uuid, AlertStatus::Open).await?;
+    }
+    util::update_embeds(uuid, ctx, db_client).await?;
+    util::update_army_embeds(uuid, ctx, db_client).await?;
 
+    let mut users = ac.get_users();
+    users.sort_unstable();
+    users.dedup();
+    util::notify_users(
+        ctx,
+        &users,
+        &format!(
+            "The alert **{}** you responded to has changed. {}",
+            ac.get_title(),
+            changes.join(" ")
+        ),
+    )
+    .await;
     Ok(())
 }
 
-async fn refresh_embeds(ctx: &Context, uuid: Uuid, db_client: &mongodb::Client) {
-    util::update_embeds(uuid, ctx, db_client).await;
+async fn cancel_alert(
+    ctx: &Context,
+    uuid: Uuid,
+    guild_id: GuildId,
+    notify: bool,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<()> {
+    let ac = db_client
+        .get_alert_connector(uuid)
+        .await?
+        .filter(|ac| ac.get_guild_ids().contains(&guild_id.0))
+        .ok_or(Error::InvalidInput(
+            "No alert with this ID was posted on this server.",
+        ))?;
+
+    db_client
+        .set_alert_status(uuid, AlertStatus::Cancelled)
+        .await?;
+    util::update_embeds(uuid, ctx, db_client).await?;
+    util::update_army_embeds(uuid, ctx, db_client).await?;
+
+    if notify {
+        let mut users = ac.get_users();
+        users.sort_unstable();
+        users.dedup();
+        util::notify_users(
+            ctx,
+            &users,
+            &format!(
+                "The alert **{}** starting {} you responded to has been cancelled.",
+                ac.get_title(),
+                ac.get_start_label()
+            ),
+        )
+        .await;
+    }
+    Ok(())
+}
+
+async fn sort_roster(
+    ctx: &Context,
+    uuid: Uuid,
+    guild_id: GuildId,
+    order: RosterOrder,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<()> {
+    get_guild_alert_connector(uuid, guild_id, db_client).await?;
+    db_client.set_roster_order(uuid, order).await?;
+    util::update_embeds(uuid, ctx, db_client).await
+}
+
+async fn refresh_embeds(
+    ctx: &Context,
+    uuid: Uuid,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<()> {
+    util::update_embeds(uuid, ctx, db_client).await
 }
 
 async fn generate_stats_embed(
     ctx: &Context,
     local: bool,
     guild_id: Option<GuildId>,
-    db_client: &mongodb::Client,
-) -> CreateEmbed {
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<CreateEmbed> {
     let mut average_gear_score = 0;
     let mut average_level = 0;
-    let guild_count = ctx.http.get_guilds(None, None).await.unwrap().len();
+    let guild_count = ctx.http.get_guilds(None, None).await?.len();
     let mut title_key = "Global".to_string();
     let mut main_hand_count = HashMap::with_capacity(WEAPONS.len());
     let mut secondary_count = HashMap::with_capacity(WEAPONS.len());
@@ -737,10 +1959,10 @@ async fn generate_stats_embed(
     }
     if local {
         if let Some(guild_id) = guild_id {
-            title_key = guild_id.name(ctx).unwrap();
-            for member in guild_id.members(ctx, None, None).await.unwrap() {
+            title_key = guild_id.name(ctx).unwrap_or(title_key);
+            for member in guild_id.members(ctx, None, None).await? {
                 if !member.user.bot {
-                    if let Some(user_data) = db_client.get_user_data(member.user.id.0).await {
+                    if let Some(user_data) = db_client.get_user_data(member.user.id.0).await? {
                         if user_data.level > 1 {
                             average_level += user_data.level as u32;
                             registered_level += 1u32;
@@ -768,7 +1990,7 @@ async fn generate_stats_embed(
             average_level /= registered_level;
         }
     } else {
-        let results = db_client.get_user_data_stats().await;
+        let results = db_client.get_user_data_stats().await?;
         average_level = results.0;
         average_gear_score = results.1;
         main_hand_count = results.2;
@@ -811,7 +2033,7 @@ async fn generate_stats_embed(
             false,
         );
 
-    embed
+    Ok(embed)
 }
 
 async fn search_trade_skills(
@@ -819,8 +2041,8 @@ async fn search_trade_skills(
     guild_id: GuildId,
     trade_skill: TradeSkill,
     level: u8,
-    db_client: &mongodb::Client,
-) -> CreateEmbed {
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<CreateEmbed> {
     let mut embed = CreateEmbed::default();
     embed.title(format!("War Helper Search - {}", trade_skill.get_label()));
     embed.description(format!("Minimum Level: {}", level));
@@ -829,9 +2051,9 @@ async fn search_trade_skills(
 
     let mut count = 100;
 
-    for member in guild_id.members(ctx, None, None).await.unwrap() {
+    for member in guild_id.members(ctx, None, None).await? {
         if !member.user.bot {
-            if let Some(user_data) = db_client.get_user_data(member.user.id.0).await {
+            if let Some(user_data) = db_client.get_user_data(member.user.id.0).await? {
                 if let Some(&user_level) = user_data.trade_skills.get(&trade_skill) {
                     if user_level >= level {
                         names = format!("{}`{}`\n", names, member.display_name());
@@ -851,7 +2073,7 @@ async fn search_trade_skills(
 
     embed.field("\u{200B}", names, false);
 
-    embed
+    Ok(embed)
 }
 
 async fn search_weapon_levels(
@@ -859,8 +2081,8 @@ async fn search_weapon_levels(
     guild_id: GuildId,
     weapon: Weapon,
     level: u8,
-    db_client: &mongodb::Client,
-) -> CreateEmbed {
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<CreateEmbed> {
     let mut embed = CreateEmbed::default();
     embed.title(format!("War Helper Search - {}", weapon.get_label()));
     embed.description(format!("Minimum Level: {}", level));
@@ -869,9 +2091,9 @@ async fn search_weapon_levels(
 
     let mut count = 100;
 
-    for member in guild_id.members(ctx, None, None).await.unwrap() {
+    for member in guild_id.members(ctx, None, None).await? {
         if !member.user.bot {
-            if let Some(user_data) = db_client.get_user_data(member.user.id.0).await {
+            if let Some(user_data) = db_client.get_user_data(member.user.id.0).await? {
                 if let Some(&user_level) = user_data.weapons.get(&weapon) {
                     if user_level >= level {
                         names = format!("{}`{}`\n", names, member.display_name());
@@ -891,15 +2113,15 @@ async fn search_weapon_levels(
 
     embed.field("\u{200B}", names, false);
 
-    embed
+    Ok(embed)
 }
 
 async fn search_gear_score(
     ctx: &Context,
     guild_id: GuildId,
     gs: u16,
-    db_client: &mongodb::Client,
-) -> CreateEmbed {
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<CreateEmbed> {
     let mut embed = CreateEmbed::default();
     embed.title("War Helper Search - Gear Score");
     embed.description(format!("Minimum GS: {}", gs));
@@ -908,9 +2130,9 @@ async fn search_gear_score(
 
     let mut count = 100;
 
-    for member in guild_id.members(ctx, None, None).await.unwrap() {
+    for member in guild_id.members(ctx, None, None).await? {
         if !member.user.bot {
-            if let Some(user_data) = db_client.get_user_data(member.user.id.0).await {
+            if let Some(user_data) = db_client.get_user_data(member.user.id.0).await? {
                 if user_data.gear_score >= gs {
                     names = format!("{}`{}`\n", names, member.display_name());
                     count -= 1;
@@ -928,15 +2150,15 @@ async fn search_gear_score(
 
     embed.field("\u{200B}", names, false);
 
-    embed
+    Ok(embed)
 }
 
 async fn search_level(
     ctx: &Context,
     guild_id: GuildId,
     level: u8,
-    db_client: &mongodb::Client,
-) -> CreateEmbed {
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<CreateEmbed> {
     let mut embed = CreateEmbed::default();
     embed.title("War Helper Search - Level");
     embed.description(format!("Minimum Level: {}", level));
@@ -945,9 +2167,9 @@ async fn search_level(
 
     let mut count = 100;
 
-    for member in guild_id.members(ctx, None, None).await.unwrap() {
+    for member in guild_id.members(ctx, None, None).await? {
         if !member.user.bot {
-            if let Some(user_data) = db_client.get_user_data(member.user.id.0).await {
+            if let Some(user_data) = db_client.get_user_data(member.user.id.0).await? {
                 if user_data.level >= level {
                     names = format!("{}`{}`\n", names, member.display_name());
                     count -= 1;
@@ -965,7 +2187,7 @@ async fn search_level(
 
     embed.field("\u{200B}", names, false);
 
-    embed
+    Ok(embed)
 }
 
 fn add_weapon_counts_to_embed(weapon_counts: HashMap<Weapon, u32>) -> String {
@@ -992,3 +2214,168 @@ fn add_weapon_counts_to_embed(weapon_counts: HashMap<Weapon, u32>) -> String {
 
     result
 }
+
+/// The resolved value of the sub command option called `name`. Discord only sends the options
+/// that were given, so reading them by position breaks once an optional one is left out.
+fn get_named_option<'a>(
+    sub_command: &'a CommandDataOption,
+    name: &str,
+) -> Option<&'a CommandDataOptionValue> {
+    sub_command
+        .options
+        .iter()
+        .find(|option| option.name == name)
+        .and_then(|option| option.resolved.as_ref())
+}
+
+/// The role quotas given to `alert` or `event`. Roles without a quota are left out.
+fn get_quotas(sub_command: &CommandDataOption) -> HashMap<RosterRole, u32> {
+    let mut quotas = HashMap::new();
+    for role in ROSTER_ROLES
+        .into_iter()
+        .filter(|role| role.is_combat_role())
+    {
+        if let Some(CommandDataOptionValue::Integer(quota)) =
+            get_named_option(sub_command, role.get_field())
+        {
+            quotas.insert(role, *quota as u32);
+        }
+    }
+    quotas
+}
+
+/// The signup requirements given to `alert` or `event`.
+fn get_requirements(sub_command: &CommandDataOption) -> Requirements {
+    Requirements {
+        min_gear_score: if let Some(CommandDataOptionValue::Integer(gear_score)) =
+            get_named_option(sub_command, "mingearscore")
+        {
+            Some(*gear_score as u16)
+        } else {
+            None
+        },
+        min_level: if let Some(CommandDataOptionValue::Integer(level)) =
+            get_named_option(sub_command, "minlevel")
+        {
+            Some(*level as u8)
+        } else {
+            None
+        },
+        required_weapon: get_named_string(sub_command, "weapon")
+            .and_then(|weapon| Weapon::try_from(weapon.to_string()).ok()),
+    }
+}
+
+fn get_named_string<'a>(sub_command: &'a CommandDataOption, name: &str) -> Option<&'a str> {
+    if let Some(CommandDataOptionValue::String(value)) = get_named_option(sub_command, name) {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// Registers all commands, for the guild in `GUILD_ID` if set and globally otherwise.
+/// Guild commands update instantly, which makes them handy while testing.
+pub async fn register_commands(ctx: &Context) -> Result<()> {
+    if let Ok(guild_id) = env::var("GUILD_ID") {
+        let guild_id = guild_id
+            .parse::<u64>()
+            .map_err(|_| Error::InvalidInput("GUILD_ID is not a valid guild ID"))?;
+        GuildId(guild_id)
+            .set_application_commands(&ctx.http, |c| {
+                c.create_application_command(create_war_command)
+                    .create_application_command(create_register_command)
+                    .create_application_command(create_war_stat_command)
+            })
+            .await?;
+    } else {
+        Command::set_global_application_commands(&ctx.http, |c| {
+            c.create_application_command(create_war_command)
+                .create_application_command(create_register_command)
+                .create_application_command(create_war_stat_command)
+        })
+        .await?;
+    }
+    Ok(())
+}
+
+fn add_weapon_choices(
+    option: &mut CreateApplicationCommandOption,
+) -> &mut CreateApplicationCommandOption {
+    option
+        .name("weapon")
+        .description("The weapon")
+        .kind(CommandOptionType::String);
+    for weapon in WEAPONS {
+        option.add_string_choice(weapon.get_label(), String::from(weapon));
+    }
+    option
+}
+
+fn add_role_choices(
+    option: &mut CreateApplicationCommandOption,
+) -> &mut CreateApplicationCommandOption {
+    option
+        .name("role")
+        .description("The roster role")
+        .kind(CommandOptionType::String);
+    for role in ROSTER_ROLES {
+        option.add_string_choice(role.get_name(), role.get_field());
+    }
+    option
+}
+
+/// Adds an optional quota for every combat role, named after the role's roster field.
+fn add_quota_options(
+    option: &mut CreateApplicationCommandOption,
+) -> &mut CreateApplicationCommandOption {
+    for role in ROSTER_ROLES
+        .into_iter()
+        .filter(|role| role.is_combat_role())
+    {
+        option.create_sub_option(|s| {
+            s.name(role.get_field())
+                .description(format!("The maximum number of {} signups", role.get_name()))
+                .kind(CommandOptionType::Integer)
+                .min_int_value(1)
+        });
+    }
+    option
+}
+
+/// Adds the optional signup requirements for combat roles.
+fn add_requirement_options(
+    option: &mut CreateApplicationCommandOption,
+) -> &mut CreateApplicationCommandOption {
+    option
+        .create_sub_option(|s| {
+            s.name("mingearscore")
+                .description("The minimum gear score to sign up for a combat role")
+                .kind(CommandOptionType::Integer)
+                .min_int_value(0)
+                .max_int_value(625)
+        })
+        .create_sub_option(|s| {
+            s.name("minlevel")
+                .description("The minimum character level to sign up for a combat role")
+                .kind(CommandOptionType::Integer)
+                .min_int_value(1)
+                .max_int_value(60)
+        })
+        .create_sub_option(|s| {
+            add_weapon_choices(s).description("A weapon members must have equipped to sign up")
+        })
+}
+
+fn add_trade_skill_choices(
+    option: &mut CreateApplicationCommandOption,
+) -> &mut CreateApplicationCommandOption {
+    option
+        .name("tradeskill")
+        .description("The trade skill")
+        .kind(CommandOptionType::String);
+    for skill in TRADE_SKILLS {
+        option.add_string_choice(skill.get_label(), String::from(skill));
+    }
+    option
+}
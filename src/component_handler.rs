@@ -0,0 +1,127 @@
+use crate::error::{Error, Result};
+use crate::roster_role::{RosterRole, ROSTER_ROLES};
+use crate::war_message::WarMessage;
+use crate::{util, DBHandler};
+use serenity::builder::CreateComponents;
+use serenity::model::application::component::ButtonStyle;
+use serenity::model::application::interaction::message_component::MessageComponentInteraction;
+use serenity::model::prelude::ReactionType;
+use serenity::prelude::Context;
+use std::str::FromStr;
+use uuid::Uuid;
+
+const SIGNUP_PREFIX: &str = "signup:";
+const WITHDRAW_ID: &str = "withdraw";
+
+/// How members sign up to newly posted alerts. Set with `SIGNUP_MODE`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum SignupMode {
+    /// A button per role plus a withdraw button below the alert.
+    #[default]
+    Buttons,
+    /// The legacy mode, one emoji reaction per role.
+    Reactions,
+}
+
+/// Adds the role buttons and the withdraw button. Discord allows at most 5 buttons per row.
+pub fn add_signup_components(components: &mut CreateComponents) -> &mut CreateComponents {
+    for row in ROSTER_ROLES.chunks(5) {
+        components.create_action_row(|r| {
+            for role in row {
+                r.create_button(|b| {
+                    b.custom_id(format!("{}{}", SIGNUP_PREFIX, role.get_field()))
+                        .label(role.get_name())
+                        .emoji(ReactionType::Unicode(role.get_emoji().to_string()))
+                        .style(if role.is_combat_role() {
+                            ButtonStyle::Primary
+                        } else {
+                            ButtonStyle::Secondary
+                        })
+                });
+            }
+            if row.len() < 5 {
+                r.create_button(|b| {
+                    b.custom_id(WITHDRAW_ID)
+                        .label("Withdraw")
+                        .style(ButtonStyle::Danger)
+                });
+            }
+            r
+        });
+    }
+    components
+}
+
+/// Handles a click on one of the signup buttons. Returns the message shown to the user.
+pub async fn handle_signup_component(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+) -> Result<String> {
+    let guild_id = component.guild_id.ok_or(Error::InvalidInput(
+        "Signups are only possible on a server.",
+    ))?;
+    let db_client = ctx.data.read().await.get::<DBHandler>().unwrap().clone();
+    let ac = db_client
+        .get_alert_connector_by_message(WarMessage::new(
+            guild_id.0,
+            component.channel_id.0,
+            component.message.id.0,
+        ))
+        .await?
+        .ok_or(Error::InvalidInput("This war alert no longer exists."))?;
+    let uuid = Uuid::from_str(&ac.code)
+        .map_err(|_| Error::InvalidInput("This war alert has an invalid code."))?;
+
+    if !ac.is_open() {
+        return Err(Error::InvalidInput("This alert no longer accepts signups."));
+    }
+
+    let user_id = component.user.id.0;
+    let msg = if component.data.custom_id == WITHDRAW_ID {
+        let role = ac.get_user_role(user_id);
+        let waiting_for = ac.get_waitlist_position(user_id).map(|(role, _)| role);
+        for role in role.iter().chain(waiting_for.iter()) {
+            let change = db_client.withdraw(uuid, user_id, *role).await?;
+            util::notify_promotions(ctx, &ac, &change.promoted).await;
+        }
+        match (role, waiting_for) {
+            (Some(role), _) => format!("You have been removed from {}.", role.get_name()),
+            (None, Some(role)) => {
+                format!(
+                    "You have been removed from the waitlist for {}.",
+                    role.get_name()
+                )
+            }
+            (None, None) => return Ok("You are not signed up for this war.".to_string()),
+        }
+    } else if let Some(role) = component
+        .data
+        .custom_id
+        .strip_prefix(SIGNUP_PREFIX)
+        .and_then(RosterRole::from_field)
+    {
+        if let Some(reason) =
+            util::check_requirements(&ac, user_id, role, db_client.as_ref()).await?
+        {
+            return Ok(reason);
+        }
+        let change = db_client.signup(uuid, user_id, role, None).await?;
+        util::notify_promotions(ctx, &ac, &change.promoted).await;
+        util::prompt_registration(ctx, user_id, db_client.as_ref()).await?;
+        if let Some(position) = change.waitlisted {
+            format!(
+                "{} is full, you are #{} on its waitlist. You will be moved in when a spot \
+                opens up.",
+                role.get_name(),
+                position
+            )
+        } else {
+            format!("You are signed up as {}.", role.get_name())
+        }
+    } else {
+        return Err(Error::InvalidInput("Unknown signup option."));
+    };
+
+    util::update_embeds(uuid, ctx, db_client.as_ref()).await?;
+    Ok(msg)
+}
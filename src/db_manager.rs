@@ -1,768 +1,628 @@
+use crate::alert_connector::RosterChange;
+use crate::alert_status::AlertStatus;
+use crate::error::{Error, Result};
+use crate::group_assignment::GroupAssignment;
+use crate::guild_settings::GuildSettings;
+use crate::roster_order::RosterOrder;
+use crate::roster_role::{RosterRole, ROSTER_ROLES};
 use crate::trade_skill::TradeSkill;
 use crate::user_data::UserData;
 use crate::war_message::WarMessage;
 use crate::weapon::{Weapon, WEAPONS};
 use crate::{async_trait, AlertConnector};
+use chrono::Utc;
+use chrono_tz::Tz;
 use futures::TryStreamExt;
 use mongodb::bson;
-use mongodb::bson::{doc, Document};
-use mongodb::options::UpdateOptions;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::{IndexOptions, UpdateOptions};
+use mongodb::IndexModel;
 use serenity::model::id::RoleId;
 use std::collections::HashMap;
 use uuid::Uuid;
 
 #[async_trait]
-pub trait DBManager {
-    async fn get_alert_connector(&self, uuid: Uuid) -> Option<AlertConnector>;
-    async fn get_alert_connectors(&self) -> Vec<AlertConnector>;
-    async fn get_alert_connectors_with_user_id(&self, user_id: u64) -> Vec<AlertConnector>;
-    async fn get_user_data(&self, user_id: u64) -> Option<UserData>;
-    async fn has_permission(&self, guild_id: u64, roles: &[RoleId]) -> bool;
-    async fn add_permission(&self, guild_id: u64, role_id: u64);
-    async fn remove_permission(&self, guild_id: u64, role_id: u64);
+pub trait DBManager: Send + Sync {
+    /// Creates the indexes the queries below rely on. Called once at startup.
+    async fn create_indexes(&self) -> Result<()>;
+    async fn get_alert_connector(&self, uuid: Uuid) -> Result<Option<AlertConnector>>;
+    async fn get_alert_connectors(&self) -> Result<Vec<AlertConnector>>;
+    /// Finds the alert that was posted as `message`.
+    async fn get_alert_connector_by_message(
+        &self,
+        message: WarMessage,
+    ) -> Result<Option<AlertConnector>>;
+    /// Finds the alerts the user responded to. Cancelled alerts are left out.
+    async fn get_alert_connectors_with_user_id(&self, user_id: u64) -> Result<Vec<AlertConnector>>;
+    /// Like [`DBManager::get_alert_connectors_with_user_id`], but only returns alerts starting
+    /// at or after `since` (a unix timestamp) that were not archived. Alerts without a stored
+    /// start were archived by migration and are left out as well.
+    async fn get_upcoming_alert_connectors_with_user_id(
+        &self,
+        user_id: u64,
+        since: i64,
+    ) -> Result<Vec<AlertConnector>>;
+    async fn get_user_data(&self, user_id: u64) -> Result<Option<UserData>>;
+    async fn has_permission(&self, guild_id: u64, roles: &[RoleId]) -> Result<bool>;
+    async fn add_permission(&self, guild_id: u64, role_id: u64) -> Result<()>;
+    async fn remove_permission(&self, guild_id: u64, role_id: u64) -> Result<()>;
+    /// The guild's settings, or the defaults if none were saved.
+    async fn get_guild_settings(&self, guild_id: u64) -> Result<GuildSettings>;
+    async fn set_guild_time_zone(&self, guild_id: u64, time_zone: Tz) -> Result<()>;
     async fn channel_contains_war_message(
         &self,
         guild_id: u64,
         channel_id: u64,
         uuid: Uuid,
-    ) -> bool;
-    async fn add_war_message(
+    ) -> Result<bool>;
+    /// Links another posted message to an existing alert.
+    async fn add_war_message(&self, uuid: Uuid, message: WarMessage) -> Result<()>;
+    /// Stores a new alert as is, including its roster, quotas, requirements and messages.
+    async fn create_alert_connector(&self, ac: &AlertConnector) -> Result<()>;
+    /// Stores the date, time, territory, title, description and start time of `ac`. A changed
+    /// start time clears the sent reminders, so the new one is reminded of again.
+    /// The roster and the posted messages are left untouched.
+    async fn update_alert_details(&self, ac: &AlertConnector) -> Result<()>;
+    async fn set_alert_status(&self, uuid: Uuid, status: AlertStatus) -> Result<()>;
+    async fn set_roster_order(&self, uuid: Uuid, order: RosterOrder) -> Result<()>;
+    /// Pins the user into an army group, or onto the bench for `None`, replacing their previous
+    /// assignment.
+    async fn set_group_assignment(&self, uuid: Uuid, assignment: GroupAssignment) -> Result<()>;
+    /// Remembers the army layout posted as `message`, replacing the one in the same channel.
+    async fn set_army_message(&self, uuid: Uuid, message: WarMessage) -> Result<()>;
+    /// Records that the reminder `offset` minutes before the alert was sent. Returns false if it
+    /// was already recorded, so a reminder is only ever sent once, even across restarts.
+    async fn mark_reminder_sent(&self, uuid: Uuid, offset: i64) -> Result<bool>;
+    /// Records that the user was asked to register. Returns false if they already were.
+    async fn mark_register_prompt_sent(&self, user_id: u64) -> Result<bool>;
+    /// Finds alerts in `status` that start before `before` (a unix timestamp).
+    async fn get_due_alert_connectors(
         &self,
-        guild_id: u64,
-        channel_id: u64,
-        message_id: u64,
-        uuid: Uuid,
-        date: &str,
-        time: &str,
-        server: &str,
-        faction: &str,
-        territory: &str,
-        title: &str,
-        r#type: u8,
-    );
-    async fn create_alert_connector(
+        status: AlertStatus,
+        before: i64,
+    ) -> Result<Vec<AlertConnector>>;
+    async fn update_main_hand(&self, user_id: u64, weapon: Weapon) -> Result<()>;
+    async fn update_secondary(&self, user_id: u64, weapon: Weapon) -> Result<()>;
+    async fn update_level(&self, user_id: u64, level: u8) -> Result<()>;
+    async fn update_gear_score(&self, user_id: u64, gear_score: u16) -> Result<()>;
+    async fn update_trade_skill(&self, user_id: u64, level: u8, skill: TradeSkill) -> Result<()>;
+    async fn update_weapon_level(&self, user_id: u64, level: u8, weapon: Weapon) -> Result<()>;
+    async fn update_username(&self, user_id: u64, username: &str) -> Result<()>;
+    /// Moves the user into `role`, taking them out of any other role on the alert in the same
+    /// update. Full roles put the user on their waitlist instead. See [`AlertConnector::signup`].
+    async fn signup(
         &self,
         uuid: Uuid,
-        date: &str,
-        time: &str,
-        server: &str,
-        faction: &str,
-        territory: &str,
-        title: &str,
-        r#type: u8,
-        guild_id: u64,
-        channel_id: u64,
-        message_id: u64,
-    ) -> AlertConnector;
-    async fn update_main_hand(&self, user_id: u64, weapon: Weapon);
-    async fn update_secondary(&self, user_id: u64, weapon: Weapon);
-    async fn update_level(&self, user_id: u64, level: u8);
-    async fn update_gear_score(&self, user_id: u64, gear_score: u16);
-    async fn update_trade_skill(&self, user_id: u64, level: u8, skill: TradeSkill);
-    async fn update_weapon_level(&self, user_id: u64, level: u8, weapon: Weapon);
-    async fn update_username(&self, user_id: u64, username: &str);
-    async fn add_tank(&self, uuid: Uuid, user_id: u64);
-    async fn add_mdps(&self, uuid: Uuid, user_id: u64);
-    async fn add_prdps(&self, uuid: Uuid, user_id: u64);
-    async fn add_erdps(&self, uuid: Uuid, user_id: u64);
-    async fn add_healer(&self, uuid: Uuid, user_id: u64);
-    async fn add_artillery(&self, uuid: Uuid, user_id: u64);
-    async fn add_tentative(&self, uuid: Uuid, user_id: u64);
-    async fn add_not_available(&self, uuid: Uuid, user_id: u64);
-    async fn remove_tank(&self, uuid: Uuid, user_id: u64);
-    async fn remove_mdps(&self, uuid: Uuid, user_id: u64);
-    async fn remove_prdps(&self, uuid: Uuid, user_id: u64);
-    async fn remove_erdps(&self, uuid: Uuid, user_id: u64);
-    async fn remove_healer(&self, uuid: Uuid, user_id: u64);
-    async fn remove_artillery(&self, uuid: Uuid, user_id: u64);
-    async fn remove_tentative(&self, uuid: Uuid, user_id: u64);
-    async fn remove_not_available(&self, uuid: Uuid, user_id: u64);
-    async fn get_user_data_stats(&self) -> (u32, u32, HashMap<Weapon, u32>, HashMap<Weapon, u32>);
+        user_id: u64,
+        role: RosterRole,
+        added_by: Option<u64>,
+    ) -> Result<RosterChange>;
+    /// Takes the user out of `role` and promotes waitlisted members into the freed spot.
+    async fn withdraw(&self, uuid: Uuid, user_id: u64, role: RosterRole) -> Result<RosterChange>;
+    /// Repairs the roster with [`AlertConnector::remove_duplicate_entries`]. Nobody is promoted.
+    async fn remove_duplicate_entries(&self, uuid: Uuid) -> Result<()>;
+    async fn get_user_data_stats(
+        &self,
+    ) -> Result<(u32, u32, HashMap<Weapon, u32>, HashMap<Weapon, u32>)>;
+}
+
+/// Matches alerts that have the user in any of their roles or on their waitlist and were not cancelled.
+fn roster_filter(user_id: u64) -> Document {
+    doc! {
+        "$or": ROSTER_ROLES
+            .iter()
+            .map(|role| doc! {format!("{}.userId", role.get_field()): user_id as i64})
+            .chain([doc! {"waitlist.userId": user_id as i64}])
+            .collect::<Vec<_>>(),
+        "status": {"$ne": String::from(AlertStatus::Cancelled)}
+    }
+}
+
+/// How often [`update_roster`] retries before giving up on a roster that keeps changing.
+const MAX_ROSTER_RETRIES: u32 = 10;
+
+/// Applies `f` to the roster of the alert and saves it if nobody else changed the roster in the
+/// meantime, retrying up to [`MAX_ROSTER_RETRIES`] times otherwise. Quotas need the whole roster,
+/// which single updates can't check.
+async fn update_roster(
+    client: &mongodb::Client,
+    uuid: Uuid,
+    f: impl Fn(&mut AlertConnector) -> RosterChange,
+) -> Result<RosterChange> {
+    let alert_connectors = client
+        .database("warhelperDB")
+        .collection::<AlertConnector>("AlertConnectors");
+    for _ in 0..=MAX_ROSTER_RETRIES {
+        let mut ac = match alert_connectors
+            .find_one(doc! {"code": uuid.to_string()}, None)
+            .await?
+        {
+            Some(ac) => ac,
+            None => return Ok(RosterChange::default()),
+        };
+        let change = f(&mut ac);
+
+        let mut roster = doc! {
+            "waitlist": bson::to_bson(&ac.waitlist)?,
+            "revision": ac.revision + 1,
+        };
+        for role in ROSTER_ROLES {
+            roster.insert(role.get_field(), bson::to_bson(ac.get_role(role))?);
+        }
+        if alert_connectors
+            .update_one(
+                doc! {"code": &ac.code, "revision": ac.revision},
+                doc! {"$set": roster},
+                None,
+            )
+            .await?
+            .matched_count
+            == 1
+        {
+            return Ok(change);
+        }
+    }
+    Err(Error::RosterBusy)
+}
+
+async fn find_alert_connectors(
+    client: &mongodb::Client,
+    filter: Document,
+) -> Result<Vec<AlertConnector>> {
+    let mut connectors = vec![];
+    let mut results = client
+        .database("warhelperDB")
+        .collection::<AlertConnector>("AlertConnectors")
+        .find(filter, None)
+        .await?;
+
+    while let Some(ac) = results.try_next().await? {
+        connectors.push(ac);
+    }
+    Ok(connectors)
+}
+
+/// Sets a single field of the user's document, creating the document if needed.
+async fn set_user_field(
+    client: &mongodb::Client,
+    user_id: u64,
+    field: &str,
+    value: Bson,
+) -> Result<()> {
+    client
+        .database("warhelperDB")
+        .collection::<Document>("UserData")
+        .update_one(
+            doc! {"_id": user_id as i64},
+            doc! {"$set": {field: value}},
+            {
+                let mut options = UpdateOptions::default();
+                options.upsert = Some(true);
+                options
+            },
+        )
+        .await?;
+    Ok(())
 }
 
 #[async_trait]
 impl DBManager for mongodb::Client {
-    async fn get_alert_connector(&self, uuid: Uuid) -> Option<AlertConnector> {
+    async fn create_indexes(&self) -> Result<()> {
+        let mut indexes = vec![
+            IndexModel::builder()
+                .keys(doc! {"code": 1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            IndexModel::builder()
+                .keys(doc! {"warMessages.MESSAGE_ID": 1})
+                .build(),
+            IndexModel::builder().keys(doc! {"startsAt": 1}).build(),
+            IndexModel::builder()
+                .keys(doc! {"status": 1, "startsAt": 1})
+                .build(),
+        ];
+        for role in ROSTER_ROLES {
+            indexes.push(
+                IndexModel::builder()
+                    .keys(doc! {format!("{}.userId", role.get_field()): 1})
+                    .build(),
+            );
+        }
+        indexes.push(
+            IndexModel::builder()
+                .keys(doc! {"waitlist.userId": 1})
+                .build(),
+        );
         self.database("warhelperDB")
+            .collection::<AlertConnector>("AlertConnectors")
+            .create_indexes(indexes, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_alert_connector(&self, uuid: Uuid) -> Result<Option<AlertConnector>> {
+        Ok(self
+            .database("warhelperDB")
             .collection::<AlertConnector>("AlertConnectors")
             .find_one(doc! {"code": uuid.to_string()}, None)
-            .await
-            .expect("Failed to get AlertConnectors collection")
+            .await?)
     }
 
-    async fn get_alert_connectors(&self) -> Vec<AlertConnector> {
+    async fn get_alert_connectors(&self) -> Result<Vec<AlertConnector>> {
         let mut connectors = vec![];
         let mut results = self
             .database("warhelperDB")
             .collection::<AlertConnector>("AlertConnectors")
             .find(None, None)
-            .await
-            .expect("Failed to get alert connectors");
+            .await?;
 
-        while let Some(ac) = results
-            .try_next()
-            .await
-            .expect("Failed to get next alert connector from cursor")
-        {
+        while let Some(ac) = results.try_next().await? {
             connectors.push(ac);
         }
-        connectors
+        Ok(connectors)
+    }
+
+    async fn get_alert_connector_by_message(
+        &self,
+        message: WarMessage,
+    ) -> Result<Option<AlertConnector>> {
+        Ok(self
+            .database("warhelperDB")
+            .collection::<AlertConnector>("AlertConnectors")
+            .find_one(
+                doc! {"warMessages": {"$elemMatch": bson::to_document(&message)?}},
+                None,
+            )
+            .await?)
     }
 
-    async fn get_alert_connectors_with_user_id(&self, user_id: u64) -> Vec<AlertConnector> {
-        self.get_alert_connectors()
-            .await
-            .into_iter()
-            .filter(|ac| ac.get_users().contains(&user_id))
-            .collect()
+    async fn get_alert_connectors_with_user_id(&self, user_id: u64) -> Result<Vec<AlertConnector>> {
+        find_alert_connectors(self, roster_filter(user_id)).await
     }
 
-    async fn get_user_data(&self, user_id: u64) -> Option<UserData> {
-        let data = self
+    async fn get_upcoming_alert_connectors_with_user_id(
+        &self,
+        user_id: u64,
+        since: i64,
+    ) -> Result<Vec<AlertConnector>> {
+        let mut filter = roster_filter(user_id);
+        filter.insert("startsAt", doc! {"$gte": since});
+        filter.insert(
+            "status",
+            doc! {"$nin": [String::from(AlertStatus::Cancelled), String::from(AlertStatus::Archived)]},
+        );
+        find_alert_connectors(self, filter).await
+    }
+
+    async fn get_user_data(&self, user_id: u64) -> Result<Option<UserData>> {
+        Ok(self
             .database("warhelperDB")
-            .collection::<Document>("UserData")
-            .find_one(doc! {format!("{}", user_id): { "$exists": true } }, None)
-            .await
-            .expect("Failed to get UserData collection");
-
-        data.map(|data| {
-            bson::from_bson(data.get(format!("{}", user_id)).unwrap().clone())
-                .expect("Failed to parse user data")
-        })
+            .collection::<UserData>("UserData")
+            .find_one(doc! {"_id": user_id as i64}, None)
+            .await?)
     }
 
-    async fn has_permission(&self, guild_id: u64, roles: &[RoleId]) -> bool {
-        let filter = doc! {  format!("{}", guild_id): { "$exists": true } };
+    async fn has_permission(&self, guild_id: u64, roles: &[RoleId]) -> Result<bool> {
         let perm = self
             .database("warhelperDB")
             .collection::<Document>("Permissions")
-            .find_one(filter, None)
-            .await
-            .unwrap();
+            .find_one(doc! {"_id": guild_id as i64}, None)
+            .await?;
 
         if let Some(entry) = perm {
-            if let Some(arr) = entry.get(format!("{}", guild_id)) {
+            if let Some(arr) = entry.get("roles") {
                 if let Ok(arr) = bson::from_bson::<Vec<u64>>(arr.clone()) {
-                    return roles.iter().any(|r| arr.contains(&r.0));
+                    return Ok(roles.iter().any(|r| arr.contains(&r.0)));
                 }
             }
         }
 
-        false
+        Ok(false)
     }
 
-    async fn add_permission(&self, guild_id: u64, role_id: u64) {
+    async fn add_permission(&self, guild_id: u64, role_id: u64) -> Result<()> {
         self.database("warhelperDB")
             .collection::<Document>("Permissions")
             .update_one(
-                doc! {
-                format!("{}", guild_id): { "$exists": true }},
-                doc! { "$addToSet": { format!("{}", guild_id): bson::to_bson(&role_id).unwrap() } },
+                doc! {"_id": guild_id as i64},
+                doc! { "$addToSet": { "roles": bson::to_bson(&role_id)? } },
                 {
                     let mut options = UpdateOptions::default();
                     options.upsert = Some(true);
                     options
                 },
             )
-            .await
-            .expect("Failed to add permissions");
+            .await?;
+        Ok(())
     }
 
-    async fn remove_permission(&self, guild_id: u64, role_id: u64) {
+    async fn remove_permission(&self, guild_id: u64, role_id: u64) -> Result<()> {
         self.database("warhelperDB")
             .collection::<Document>("Permissions")
             .update_one(
-                doc! {
-                format!("{}", guild_id): { "$exists": true }},
-                doc! { "$pull": { format!("{}", guild_id): bson::to_bson(&role_id).unwrap() } },
+                doc! {"_id": guild_id as i64},
+                doc! { "$pull": { "roles": bson::to_bson(&role_id)? } },
                 None,
             )
-            .await
-            .expect("Failed to add permissions");
+            .await?;
+        Ok(())
     }
 
-    async fn channel_contains_war_message(
-        &self,
-        guild_id: u64,
-        channel_id: u64,
-        uuid: Uuid,
-    ) -> bool {
-        if let Some(ac) = self.get_alert_connector(uuid).await {
-            ac.channel_contains_war_message(guild_id, channel_id)
-        } else {
-            false
-        }
-    }
-
-    async fn add_war_message(
-        &self,
-        guild_id: u64,
-        channel_id: u64,
-        message_id: u64,
-        uuid: Uuid,
-        date: &str,
-        time: &str,
-        server: &str,
-        faction: &str,
-        territory: &str,
-        title: &str,
-        r#type: u8,
-    ) {
-        if self.get_alert_connector(uuid).await.is_some() {
-            self.database("warhelperDB").collection::<AlertConnector>("AlertConnectors")
-                .update_one(doc! { "code": uuid.to_string() }
-                            , doc! {
-                        "$addToSet": {
-                            "warMessages":
-                            bson::to_bson(&WarMessage::new(guild_id, channel_id, message_id)).unwrap()
-                        }
-                    }, None)
-                .await.expect("Failed to update Alert Connector with War Message");
-        } else {
-            self.create_alert_connector(
-                uuid, date, time, server, faction, territory, title, r#type, guild_id, channel_id,
-                message_id,
-            )
-            .await;
-        };
-    }
-
-    async fn create_alert_connector(
-        &self,
-        uuid: Uuid,
-        date: &str,
-        time: &str,
-        server: &str,
-        faction: &str,
-        territory: &str,
-        title: &str,
-        r#type: u8,
-        guild_id: u64,
-        channel_id: u64,
-        message_id: u64,
-    ) -> AlertConnector {
-        let ac = AlertConnector {
-            code: uuid.to_string(),
-            date: date.to_string(),
-            time: time.to_string(),
-            server: server.to_string(),
-            faction: faction.to_string(),
-            territory: territory.to_string(),
-            title: title.to_string(),
-            r#type,
-            tanks: Default::default(),
-            erdps: Default::default(),
-            prdps: Default::default(),
-            mdps: Default::default(),
-            healers: Default::default(),
-            tentative: Default::default(),
-            not_available: Default::default(),
-            artillery: Default::default(),
-            war_messages: vec![WarMessage::new(guild_id, channel_id, message_id)],
-        };
-        self.database("warhelperDB")
-            .collection::<AlertConnector>("AlertConnectors")
-            .insert_one(ac.clone(), None)
-            .await
-            .expect("Failed to insert alert connector");
-        ac
+    async fn get_guild_settings(&self, guild_id: u64) -> Result<GuildSettings> {
+        Ok(self
+            .database("warhelperDB")
+            .collection::<GuildSettings>("GuildSettings")
+            .find_one(doc! {"_id": guild_id as i64}, None)
+            .await?
+            .unwrap_or_default())
     }
 
-    async fn update_main_hand(&self, user_id: u64, weapon: Weapon) {
+    async fn set_guild_time_zone(&self, guild_id: u64, time_zone: Tz) -> Result<()> {
         self.database("warhelperDB")
-            .collection::<Document>("UserData")
+            .collection::<Document>("GuildSettings")
             .update_one(
-                doc! {
-                    format!("{}", user_id): {
-                        "$exists": true
-                    }
-                },
-                doc! {
-                    "$set": {
-                        format!("{}.mainHand", user_id): <Weapon as Into<String>>::into(weapon)
-                    }
-                },
+                doc! {"_id": guild_id as i64},
+                doc! {"$set": {"timeZone": time_zone.name()}},
                 {
                     let mut options = UpdateOptions::default();
                     options.upsert = Some(true);
                     options
                 },
             )
-            .await
-            .expect("Failed to update user data main hand");
+            .await?;
+        Ok(())
     }
 
-    async fn update_secondary(&self, user_id: u64, weapon: Weapon) {
-        self.database("warhelperDB")
-            .collection::<Document>("UserData")
-            .update_one(
-                doc! {
-                    format!("{}", user_id): {
-                        "$exists": true
-                    }
-                },
-                doc! {
-                    "$set": {
-                        format!("{}.secondary", user_id): <Weapon as Into<String>>::into(weapon)
-                    }
-                },
-                {
-                    let mut options = UpdateOptions::default();
-                    options.upsert = Some(true);
-                    options
-                },
-            )
-            .await
-            .expect("Failed to update user data secondary");
+    async fn channel_contains_war_message(
+        &self,
+        guild_id: u64,
+        channel_id: u64,
+        uuid: Uuid,
+    ) -> Result<bool> {
+        if let Some(ac) = self.get_alert_connector(uuid).await? {
+            Ok(ac.channel_contains_war_message(guild_id, channel_id))
+        } else {
+            Ok(false)
+        }
     }
 
-    async fn update_level(&self, user_id: u64, level: u8) {
+    async fn add_war_message(&self, uuid: Uuid, message: WarMessage) -> Result<()> {
         self.database("warhelperDB")
-            .collection::<Document>("UserData")
+            .collection::<AlertConnector>("AlertConnectors")
             .update_one(
-                doc! {
-                    format!("{}", user_id): {
-                        "$exists": true
-                    }
-                },
-                doc! {
-                    "$set": {
-                        format!("{}.level", user_id): bson::to_bson(&level).unwrap()
-                    }
-                },
-                {
-                    let mut options = UpdateOptions::default();
-                    options.upsert = Some(true);
-                    options
-                },
+                doc! {"code": uuid.to_string()},
+                doc! {"$addToSet": {"warMessages": bson::to_bson(&message)?}},
+                None,
             )
-            .await
-            .expect("Failed to update user data level");
+            .await?;
+        Ok(())
     }
 
-    async fn update_gear_score(&self, user_id: u64, gear_score: u16) {
+    async fn create_alert_connector(&self, ac: &AlertConnector) -> Result<()> {
         self.database("warhelperDB")
-            .collection::<Document>("UserData")
-            .update_one(
-                doc! {
-                    format!("{}", user_id): {
-                        "$exists": true
-                    }
-                },
-                doc! {
-                    "$set": {
-                        format!("{}.gearScore", user_id): bson::to_bson(&gear_score).unwrap()
-                    }
-                },
-                {
-                    let mut options = UpdateOptions::default();
-                    options.upsert = Some(true);
-                    options
-                },
-            )
-            .await
-            .expect("Failed to update user data gear score");
+            .collection::<AlertConnector>("AlertConnectors")
+            .insert_one(ac, None)
+            .await?;
+        Ok(())
     }
 
-    async fn update_trade_skill(&self, user_id: u64, level: u8, skill: TradeSkill) {
+    async fn update_alert_details(&self, ac: &AlertConnector) -> Result<()> {
         self.database("warhelperDB")
-            .collection::<Document>("UserData")
+            .collection::<AlertConnector>("AlertConnectors")
             .update_one(
-                doc! {
-                    format!("{}", user_id): {
-                        "$exists": true
-                    }
-                },
-                doc! {
+                doc! {"code": &ac.code},
+                // A pipeline, so the sent reminders can be compared with the stored start.
+                // Values are wrapped in `$literal` as text starting with `$` would be read as a
+                // field path otherwise.
+                vec![doc! {
                     "$set": {
-                        format!("{}.tradeSkills.{}", user_id, <TradeSkill as Into<String>>::into(skill)): bson::to_bson(&level).unwrap()
+                        "remindersSent": {"$cond": [
+                            {"$eq": ["$startsAt", {"$literal": ac.starts_at}]},
+                            "$remindersSent",
+                            [],
+                        ]},
+                        "date": {"$literal": &ac.date},
+                        "time": {"$literal": &ac.time},
+                        "territory": {"$literal": &ac.territory},
+                        "title": {"$literal": &ac.title},
+                        "description": {"$literal": &ac.description},
+                        "startsAt": {"$literal": ac.starts_at},
                     }
-                },
-                {
-                    let mut options = UpdateOptions::default();
-                    options.upsert = Some(true);
-                    options
-                },
+                }],
+                None,
             )
-            .await
-            .expect("Failed to update user data trade skill");
+            .await?;
+        Ok(())
     }
 
-    async fn update_weapon_level(&self, user_id: u64, level: u8, weapon: Weapon) {
+    async fn set_alert_status(&self, uuid: Uuid, status: AlertStatus) -> Result<()> {
         self.database("warhelperDB")
-            .collection::<Document>("UserData")
+            .collection::<AlertConnector>("AlertConnectors")
             .update_one(
-                doc! {
-                    format!("{}", user_id): {
-                        "$exists": true
-                    }
-                },
-                doc! {
-                    "$set": {
-                        format!("{}.weapons.{}", user_id, <Weapon as Into<String>>::into(weapon)): bson::to_bson(&level).unwrap()
-                    }
-                },
-                {
-                    let mut options = UpdateOptions::default();
-                    options.upsert = Some(true);
-                    options
-                },
+                doc! {"code": uuid.to_string()},
+                doc! {"$set": {"status": String::from(status)}},
+                None,
             )
-            .await
-            .expect("Failed to update user data weapon level");
+            .await?;
+        Ok(())
     }
 
-    async fn update_username(&self, user_id: u64, username: &str) {
+    async fn set_roster_order(&self, uuid: Uuid, order: RosterOrder) -> Result<()> {
         self.database("warhelperDB")
-            .collection::<Document>("UserData")
+            .collection::<AlertConnector>("AlertConnectors")
             .update_one(
-                doc! {
-                    format!("{}", user_id): {
-                        "$exists": true
-                    }
-                },
-                doc! {
-                    "$set": {
-                        format!("{}.username", user_id): format!("{}", username)
-                    }
-                },
-                {
-                    let mut options = UpdateOptions::default();
-                    options.upsert = Some(true);
-                    options
-                },
+                doc! {"code": uuid.to_string()},
+                doc! {"$set": {"rosterOrder": String::from(order)}},
+                None,
             )
-            .await
-            .expect("Failed to update username");
+            .await?;
+        Ok(())
     }
 
-    async fn add_tank(&self, uuid: Uuid, user_id: u64) {
-        self.database("warhelperDB")
-            .collection::<AlertConnector>("AlertConnectors")
+    async fn set_group_assignment(&self, uuid: Uuid, assignment: GroupAssignment) -> Result<()> {
+        let alert_connectors = self
+            .database("warhelperDB")
+            .collection::<AlertConnector>("AlertConnectors");
+        alert_connectors
             .update_one(
-                doc! {
-                    "code": format!("{}", uuid)
-                },
-                doc! {
-                    "$addToSet": {
-                        "tanks": bson::to_bson(&user_id).unwrap()
-                    }
-                },
-                {
-                    let mut options = UpdateOptions::default();
-                    options.upsert = Some(true);
-                    options
-                },
+                doc! {"code": uuid.to_string()},
+                doc! {"$pull": {"assignments": {"userId": assignment.user_id as i64}}},
+                None,
             )
-            .await
-            .expect("Failed to update tank list in alert connector");
-    }
-
-    async fn add_mdps(&self, uuid: Uuid, user_id: u64) {
-        self.database("warhelperDB")
-            .collection::<AlertConnector>("AlertConnectors")
+            .await?;
+        alert_connectors
             .update_one(
-                doc! {
-                    "code": format!("{}", uuid)
-                },
-                doc! {
-                    "$addToSet": {
-                        "mdps": bson::to_bson(&user_id).unwrap()
-                    }
-                },
-                {
-                    let mut options = UpdateOptions::default();
-                    options.upsert = Some(true);
-                    options
-                },
+                doc! {"code": uuid.to_string()},
+                doc! {"$push": {"assignments": bson::to_bson(&assignment)?}},
+                None,
             )
-            .await
-            .expect("Failed to update mdps list in alert connector");
+            .await?;
+        Ok(())
     }
 
-    async fn add_prdps(&self, uuid: Uuid, user_id: u64) {
-        self.database("warhelperDB")
-            .collection::<AlertConnector>("AlertConnectors")
+    async fn set_army_message(&self, uuid: Uuid, message: WarMessage) -> Result<()> {
+        let alert_connectors = self
+            .database("warhelperDB")
+            .collection::<AlertConnector>("AlertConnectors");
+        alert_connectors
             .update_one(
+                doc! {"code": uuid.to_string()},
                 doc! {
-                    "code": format!("{}", uuid)
-                },
-                doc! {
-                    "$addToSet": {
-                        "prdps": bson::to_bson(&user_id).unwrap()
+                    "$pull": {
+                        "armyMessages": {
+                            "GUILD_ID": message.get_guild_id() as i64,
+                            "CHANNEL_ID": message.get_channel_id() as i64
+                        }
                     }
                 },
-                {
-                    let mut options = UpdateOptions::default();
-                    options.upsert = Some(true);
-                    options
-                },
+                None,
             )
-            .await
-            .expect("Failed to update prdps list in alert connector");
-    }
-
-    async fn add_erdps(&self, uuid: Uuid, user_id: u64) {
-        self.database("warhelperDB")
-            .collection::<AlertConnector>("AlertConnectors")
+            .await?;
+        alert_connectors
             .update_one(
-                doc! {
-                    "code": format!("{}", uuid)
-                },
-                doc! {
-                    "$addToSet": {
-                        "erdps": bson::to_bson(&user_id).unwrap()
-                    }
-                },
-                {
-                    let mut options = UpdateOptions::default();
-                    options.upsert = Some(true);
-                    options
-                },
+                doc! {"code": uuid.to_string()},
+                doc! {"$push": {"armyMessages": bson::to_bson(&message)?}},
+                None,
             )
-            .await
-            .expect("Failed to update erdps list in alert connector");
+            .await?;
+        Ok(())
     }
 
-    async fn add_healer(&self, uuid: Uuid, user_id: u64) {
-        self.database("warhelperDB")
-            .collection::<AlertConnector>("AlertConnectors")
-            .update_one(
-                doc! {
-                    "code": format!("{}", uuid)
-                },
-                doc! {
-                    "$addToSet": {
-                        "healers": bson::to_bson(&user_id).unwrap()
-                    }
-                },
-                {
-                    let mut options = UpdateOptions::default();
-                    options.upsert = Some(true);
-                    options
-                },
-            )
-            .await
-            .expect("Failed to update healer list in alert connector");
+    async fn get_due_alert_connectors(
+        &self,
+        status: AlertStatus,
+        before: i64,
+    ) -> Result<Vec<AlertConnector>> {
+        find_alert_connectors(
+            self,
+            doc! {"status": String::from(status), "startsAt": {"$lt": before}},
+        )
+        .await
     }
 
-    async fn add_artillery(&self, uuid: Uuid, user_id: u64) {
-        self.database("warhelperDB")
+    async fn mark_reminder_sent(&self, uuid: Uuid, offset: i64) -> Result<bool> {
+        Ok(self
+            .database("warhelperDB")
             .collection::<AlertConnector>("AlertConnectors")
             .update_one(
-                doc! {
-                    "code": format!("{}", uuid)
-                },
-                doc! {
-                    "$addToSet": {
-                        "artillery": bson::to_bson(&user_id).unwrap()
-                    }
-                },
-                {
-                    let mut options = UpdateOptions::default();
-                    options.upsert = Some(true);
-                    options
-                },
+                doc! {"code": uuid.to_string(), "remindersSent": {"$ne": offset}},
+                doc! {"$addToSet": {"remindersSent": offset}},
+                None,
             )
-            .await
-            .expect("Failed to update artillery list in alert connector");
+            .await?
+            .modified_count
+            == 1)
     }
 
-    async fn add_tentative(&self, uuid: Uuid, user_id: u64) {
-        self.database("warhelperDB")
-            .collection::<AlertConnector>("AlertConnectors")
+    async fn mark_register_prompt_sent(&self, user_id: u64) -> Result<bool> {
+        Ok(self
+            .database("warhelperDB")
+            .collection::<Document>("RegisterPrompts")
             .update_one(
-                doc! {
-                    "code": format!("{}", uuid)
-                },
-                doc! {
-                    "$addToSet": {
-                        "tentative": bson::to_bson(&user_id).unwrap()
-                    }
-                },
+                doc! {"_id": user_id as i64},
+                doc! {"$setOnInsert": {"sentAt": Utc::now().timestamp()}},
                 {
                     let mut options = UpdateOptions::default();
                     options.upsert = Some(true);
                     options
                 },
             )
-            .await
-            .expect("Failed to update tentative list in alert connector");
+            .await?
+            .upserted_id
+            .is_some())
     }
 
-    async fn add_not_available(&self, uuid: Uuid, user_id: u64) {
-        self.database("warhelperDB")
-            .collection::<AlertConnector>("AlertConnectors")
-            .update_one(
-                doc! {
-                    "code": format!("{}", uuid)
-                },
-                doc! {
-                    "$addToSet": {
-                        "notAvailable": bson::to_bson(&user_id).unwrap()
-                    }
-                },
-                {
-                    let mut options = UpdateOptions::default();
-                    options.upsert = Some(true);
-                    options
-                },
-            )
-            .await
-            .expect("Failed to update not available list in alert connector");
+    async fn update_main_hand(&self, user_id: u64, weapon: Weapon) -> Result<()> {
+        set_user_field(self, user_id, "mainHand", String::from(weapon).into()).await
     }
 
-    async fn remove_tank(&self, uuid: Uuid, user_id: u64) {
-        self.database("warhelperDB")
-            .collection::<AlertConnector>("AlertConnectors")
-            .update_one(
-                doc! {
-                    "code": format!("{}", uuid)
-                },
-                doc! {
-                    "$pull": {
-                        "tanks": bson::to_bson(&user_id).unwrap()
-                    }
-                },
-                None,
-            )
-            .await
-            .expect("Failed to update tank list in alert connector");
+    async fn update_secondary(&self, user_id: u64, weapon: Weapon) -> Result<()> {
+        set_user_field(self, user_id, "secondary", String::from(weapon).into()).await
     }
 
-    async fn remove_mdps(&self, uuid: Uuid, user_id: u64) {
-        self.database("warhelperDB")
-            .collection::<AlertConnector>("AlertConnectors")
-            .update_one(
-                doc! {
-                    "code": format!("{}", uuid)
-                },
-                doc! {
-                    "$pull": {
-                        "mdps": bson::to_bson(&user_id).unwrap()
-                    }
-                },
-                None,
-            )
-            .await
-            .expect("Failed to update mdps list in alert connector");
+    async fn update_level(&self, user_id: u64, level: u8) -> Result<()> {
+        set_user_field(self, user_id, "level", bson::to_bson(&level)?).await
     }
 
-    async fn remove_prdps(&self, uuid: Uuid, user_id: u64) {
-        self.database("warhelperDB")
-            .collection::<AlertConnector>("AlertConnectors")
-            .update_one(
-                doc! {
-                    "code": format!("{}", uuid)
-                },
-                doc! {
-                    "$pull": {
-                        "prdps": bson::to_bson(&user_id).unwrap()
-                    }
-                },
-                None,
-            )
-            .await
-            .expect("Failed to update prdps list in alert connector");
+    async fn update_gear_score(&self, user_id: u64, gear_score: u16) -> Result<()> {
+        set_user_field(self, user_id, "gearScore", bson::to_bson(&gear_score)?).await
     }
 
-    async fn remove_erdps(&self, uuid: Uuid, user_id: u64) {
-        self.database("warhelperDB")
-            .collection::<AlertConnector>("AlertConnectors")
-            .update_one(
-                doc! {
-                    "code": format!("{}", uuid)
-                },
-                doc! {
-                    "$pull": {
-                        "erdps": bson::to_bson(&user_id).unwrap()
-                    }
-                },
-                None,
-            )
-            .await
-            .expect("Failed to update erdps list in alert connector");
+    async fn update_trade_skill(&self, user_id: u64, level: u8, skill: TradeSkill) -> Result<()> {
+        set_user_field(
+            self,
+            user_id,
+            &format!("tradeSkills.{}", String::from(skill)),
+            bson::to_bson(&level)?,
+        )
+        .await
     }
 
-    async fn remove_healer(&self, uuid: Uuid, user_id: u64) {
-        self.database("warhelperDB")
-            .collection::<AlertConnector>("AlertConnectors")
-            .update_one(
-                doc! {
-                    "code": format!("{}", uuid)
-                },
-                doc! {
-                    "$pull": {
-                        "healers": bson::to_bson(&user_id).unwrap()
-                    }
-                },
-                None,
-            )
-            .await
-            .expect("Failed to update healer list in alert connector");
+    async fn update_weapon_level(&self, user_id: u64, level: u8, weapon: Weapon) -> Result<()> {
+        set_user_field(
+            self,
+            user_id,
+            &format!("weapons.{}", String::from(weapon)),
+            bson::to_bson(&level)?,
+        )
+        .await
     }
 
-    async fn remove_artillery(&self, uuid: Uuid, user_id: u64) {
-        self.database("warhelperDB")
-            .collection::<AlertConnector>("AlertConnectors")
-            .update_one(
-                doc! {
-                    "code": format!("{}", uuid)
-                },
-                doc! {
-                    "$pull": {
-                        "artillery": bson::to_bson(&user_id).unwrap()
-                    }
-                },
-                None,
-            )
-            .await
-            .expect("Failed to update artillery list in alert connector");
+    async fn update_username(&self, user_id: u64, username: &str) -> Result<()> {
+        set_user_field(self, user_id, "username", username.into()).await
     }
 
-    async fn remove_tentative(&self, uuid: Uuid, user_id: u64) {
-        self.database("warhelperDB")
-            .collection::<AlertConnector>("AlertConnectors")
-            .update_one(
-                doc! {
-                    "code": format!("{}", uuid)
-                },
-                doc! {
-                    "$pull": {
-                        "tentative": bson::to_bson(&user_id).unwrap()
-                    }
-                },
-                None,
-            )
-            .await
-            .expect("Failed to update tentative list in alert connector");
+    async fn signup(
+        &self,
+        uuid: Uuid,
+        user_id: u64,
+        role: RosterRole,
+        added_by: Option<u64>,
+    ) -> Result<RosterChange> {
+        update_roster(self, uuid, |ac| ac.signup(user_id, role, added_by)).await
     }
 
-    async fn remove_not_available(&self, uuid: Uuid, user_id: u64) {
-        self.database("warhelperDB")
-            .collection::<AlertConnector>("AlertConnectors")
-            .update_one(
-                doc! {
-                    "code": format!("{}", uuid)
-                },
-                doc! {
-                    "$pull": {
-                        "notAvailable": bson::to_bson(&user_id).unwrap()
-                    }
-                },
-                None,
-            )
-            .await
-            .expect("Failed to update not available list in alert connector");
+    async fn withdraw(&self, uuid: Uuid, user_id: u64, role: RosterRole) -> Result<RosterChange> {
+        update_roster(self, uuid, |ac| ac.withdraw(user_id, role)).await
     }
 
-    async fn get_user_data_stats(&self) -> (u32, u32, HashMap<Weapon, u32>, HashMap<Weapon, u32>) {
+    async fn remove_duplicate_entries(&self, uuid: Uuid) -> Result<()> {
+        update_roster(self, uuid, |ac| {
+            ac.remove_duplicate_entries();
+            RosterChange::default()
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn get_user_data_stats(
+        &self,
+    ) -> Result<(u32, u32, HashMap<Weapon, u32>, HashMap<Weapon, u32>)> {
         let mut user_data = self
             .database("warhelperDB")
             .collection::<Document>("UserData")
             .find(None, None)
-            .await
-            .expect("Failed to get user data collection");
+            .await?;
         let mut total_level = 0;
         let mut level_count = 0;
         let mut total_gear_score = 0;
@@ -773,13 +633,8 @@ impl DBManager for mongodb::Client {
             main_hand_count.insert(weapon, 0);
             secondary_count.insert(weapon, 0);
         }
-        while let Some(data) = user_data
-            .try_next()
-            .await
-            .expect("Failed to get next document")
-        {
-            let inner = data.values().nth(1).unwrap();
-            if let Ok(user_data) = bson::from_bson::<UserData>(inner.clone()) {
+        while let Some(data) = user_data.try_next().await? {
+            if let Ok(user_data) = bson::from_document::<UserData>(data) {
                 if user_data.level > 1 {
                     total_level += user_data.level as u32;
                     level_count += 1;
@@ -802,11 +657,11 @@ impl DBManager for mongodb::Client {
         if level_count == 0 {
             level_count = 1;
         }
-        (
+        Ok((
             total_level / level_count,
             total_gear_score / gear_score_count,
             main_hand_count,
             secondary_count,
-        )
+        ))
     }
 }
@@ -0,0 +1,78 @@
+use mongodb::bson;
+use std::fmt::{Display, Formatter};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Mongo(mongodb::error::Error),
+    BsonSerialize(bson::ser::Error),
+    BsonDeserialize(bson::de::Error),
+    Sqlite(rusqlite::Error),
+    Discord(Box<serenity::Error>),
+    /// A blocking database task panicked or was cancelled.
+    Task(tokio::task::JoinError),
+    LockPoisoned,
+    /// The roster kept changing while an update to it was being saved.
+    RosterBusy,
+    /// The user supplied input that could not be used. The message is shown to the user as is.
+    InvalidInput(&'static str),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Mongo(why) => write!(f, "The database request failed: {}", why),
+            Error::BsonSerialize(why) => {
+                write!(f, "Failed to encode data for the database: {}", why)
+            }
+            Error::BsonDeserialize(why) => {
+                write!(f, "Failed to read stored data from the database: {}", why)
+            }
+            Error::Sqlite(why) => write!(f, "The database request failed: {}", why),
+            Error::Discord(why) => write!(f, "The Discord request failed: {}", why),
+            Error::Task(why) => write!(f, "The database task failed: {}", why),
+            Error::LockPoisoned => write!(f, "The in-memory database is unavailable"),
+            Error::RosterBusy => write!(f, "The roster is busy, please try again"),
+            Error::InvalidInput(why) => write!(f, "{}", why),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<mongodb::error::Error> for Error {
+    fn from(why: mongodb::error::Error) -> Self {
+        Error::Mongo(why)
+    }
+}
+
+impl From<bson::ser::Error> for Error {
+    fn from(why: bson::ser::Error) -> Self {
+        Error::BsonSerialize(why)
+    }
+}
+
+impl From<bson::de::Error> for Error {
+    fn from(why: bson::de::Error) -> Self {
+        Error::BsonDeserialize(why)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(why: rusqlite::Error) -> Self {
+        Error::Sqlite(why)
+    }
+}
+
+impl From<serenity::Error> for Error {
+    fn from(why: serenity::Error) -> Self {
+        Error::Discord(Box::new(why))
+    }
+}
+
+impl From<tokio::task::JoinError> for Error {
+    fn from(why: tokio::task::JoinError) -> Self {
+        Error::Task(why)
+    }
+}
@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+/// A member an officer pinned into a group of the army, or onto the bench.
+#[derive(Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+pub struct GroupAssignment {
+    #[serde(rename = "userId")]
+    pub user_id: u64,
+    /// The group number starting at 1, or `None` for standby.
+    pub group: Option<u8>,
+}
+
+impl GroupAssignment {
+    pub fn new(user_id: u64, group: Option<u8>) -> Self {
+        Self { user_id, group }
+    }
+}
@@ -0,0 +1,16 @@
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+/// Configuration an administrator can change per guild.
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct GuildSettings {
+    /// IANA name of the time zone alert times are entered in. Empty means UTC.
+    #[serde(rename = "timeZone", default)]
+    pub time_zone: String,
+}
+
+impl GuildSettings {
+    pub fn get_time_zone(&self) -> Tz {
+        self.time_zone.parse().unwrap_or(Tz::UTC)
+    }
+}
@@ -1,20 +1,39 @@
 #![allow(dead_code)]
 #![allow(clippy::too_many_arguments)]
 mod alert_connector;
+mod alert_status;
+mod army;
 mod command_handler;
+mod component_handler;
 mod db_manager;
+mod error;
+mod group_assignment;
+mod guild_settings;
+mod memory_db;
+mod migration;
+mod requirements;
+mod roster_entry;
+mod roster_order;
+mod roster_role;
+mod scheduler;
+mod sqlite_db;
 mod trade_skill;
 mod user_data;
 mod util;
+mod waitlist_entry;
 mod war_message;
 mod weapon;
 
 use crate::alert_connector::AlertConnector;
 use crate::command_handler::{
-    handle_register_command, handle_war_command, handle_war_stat_command,
+    handle_register_command, handle_war_command, handle_war_stat_command, register_commands,
 };
+use crate::component_handler::{handle_signup_component, SignupMode};
 use crate::db_manager::DBManager;
-use crate::util::REACTIONS;
+use crate::error::{Error, Result};
+use crate::memory_db::MemoryDB;
+use crate::roster_role::RosterRole;
+use crate::sqlite_db::SqliteDB;
 use crate::war_message::WarMessage;
 use mongodb::bson::doc;
 use mongodb::options::ClientOptions;
@@ -22,155 +41,56 @@ use serenity::builder::CreateEmbed;
 use serenity::client::{Context, EventHandler};
 use serenity::model::application::interaction::{Interaction, InteractionResponseType};
 use serenity::model::channel::Reaction;
+use serenity::model::gateway::Ready;
 use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::prelude::interaction::message_component::MessageComponentInteraction;
 use serenity::model::prelude::ReactionType;
 use serenity::prelude::{GatewayIntents, TypeMapKey};
 use serenity::{async_trait, Client};
 use std::env;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use uuid::Uuid;
 
-struct Handler;
+struct Handler {
+    /// `ready` fires again on every reconnect, but the scheduler must only run once.
+    scheduler_started: AtomicBool,
+}
 
 struct DBHandler;
 
 impl TypeMapKey for DBHandler {
-    type Value = mongodb::Client;
+    type Value = Arc<dyn DBManager>;
+}
+
+struct SignupModeKey;
+
+impl TypeMapKey for SignupModeKey {
+    type Value = SignupMode;
 }
 
 #[async_trait]
 impl EventHandler for Handler {
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        println!("{} is connected", ready.user.name);
+        if let Err(why) = register_commands(&ctx).await {
+            println!("Failed to register application commands: {}", why);
+        }
+        if !self.scheduler_started.swap(true, Ordering::SeqCst) {
+            tokio::spawn(scheduler::run_scheduler(ctx));
+        }
+    }
+
     async fn reaction_add(&self, ctx: Context, add_reaction: Reaction) {
-        if !add_reaction.user(&ctx).await.unwrap().bot {
-            if let Some(guild_id) = add_reaction.guild_id {
-                if let Some(user_id) = add_reaction.user_id {
-                    let db_client = ctx.data.read().await.get::<DBHandler>().unwrap().clone();
-                    let embeds = add_reaction.message(&ctx).await.unwrap().embeds;
-                    if let Some(embed) = embeds.first() {
-                        if let Some(footer) = embed.footer.as_ref() {
-                            let uuid =
-                                Uuid::from_str(&footer.text.chars().take(36).collect::<String>())
-                                    .unwrap();
-                            if let Some(ac) = db_client.get_alert_connector(uuid).await {
-                                if ac.war_messages.contains(&WarMessage::new(
-                                    guild_id.0,
-                                    add_reaction.channel_id.0,
-                                    add_reaction.message_id.0,
-                                )) && !ac.get_users().contains(&user_id.0)
-                                {
-                                    if let ReactionType::Unicode(emoji) = add_reaction.emoji {
-                                        if let Some((i, _)) = REACTIONS
-                                            .iter()
-                                            .enumerate()
-                                            .find(|(_, c)| c.to_string() == emoji)
-                                        {
-                                            match i {
-                                                0 => {
-                                                    db_client.add_tank(uuid, user_id.0).await;
-                                                }
-                                                1 => {
-                                                    db_client.add_mdps(uuid, user_id.0).await;
-                                                }
-                                                2 => {
-                                                    db_client.add_prdps(uuid, user_id.0).await;
-                                                }
-                                                3 => {
-                                                    db_client.add_erdps(uuid, user_id.0).await;
-                                                }
-                                                4 => {
-                                                    db_client.add_healer(uuid, user_id.0).await;
-                                                }
-                                                5 => {
-                                                    db_client.add_artillery(uuid, user_id.0).await;
-                                                }
-                                                6 => {
-                                                    db_client.add_tentative(uuid, user_id.0).await;
-                                                }
-                                                7 => {
-                                                    db_client
-                                                        .add_not_available(uuid, user_id.0)
-                                                        .await;
-                                                }
-                                                _ => {}
-                                            }
-                                            util::update_embeds(uuid, &ctx, &db_client).await;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+        if let Err(why) = handle_reaction_add(&ctx, &add_reaction).await {
+            report_reaction_error(&ctx, &add_reaction, why).await;
         }
     }
 
     async fn reaction_remove(&self, ctx: Context, removed_reaction: Reaction) {
-        if !removed_reaction.user(&ctx).await.unwrap().bot {
-            if let Some(guild_id) = removed_reaction.guild_id {
-                if let Some(user_id) = removed_reaction.user_id {
-                    let db_client = ctx.data.read().await.get::<DBHandler>().unwrap().clone();
-                    let embeds = removed_reaction.message(&ctx).await.unwrap().embeds;
-                    if let Some(embed) = embeds.first() {
-                        if let Some(footer) = embed.footer.as_ref() {
-                            let uuid =
-                                Uuid::from_str(&footer.text.chars().take(36).collect::<String>())
-                                    .unwrap();
-                            if let Some(ac) = db_client.get_alert_connector(uuid).await {
-                                if ac.war_messages.contains(&WarMessage::new(
-                                    guild_id.0,
-                                    removed_reaction.channel_id.0,
-                                    removed_reaction.message_id.0,
-                                )) && ac.get_users().contains(&user_id.0)
-                                {
-                                    if let ReactionType::Unicode(emoji) = removed_reaction.emoji {
-                                        if let Some((i, _)) = REACTIONS
-                                            .iter()
-                                            .enumerate()
-                                            .find(|(_, c)| c.to_string() == emoji)
-                                        {
-                                            match i {
-                                                0 => {
-                                                    db_client.remove_tank(uuid, user_id.0).await;
-                                                }
-                                                1 => {
-                                                    db_client.remove_mdps(uuid, user_id.0).await;
-                                                }
-                                                2 => {
-                                                    db_client.remove_prdps(uuid, user_id.0).await;
-                                                }
-                                                3 => {
-                                                    db_client.remove_erdps(uuid, user_id.0).await;
-                                                }
-                                                4 => {
-                                                    db_client.remove_healer(uuid, user_id.0).await;
-                                                }
-                                                5 => {
-                                                    db_client
-                                                        .remove_artillery(uuid, user_id.0)
-                                                        .await;
-                                                }
-                                                6 => {
-                                                    db_client
-                                                        .remove_tentative(uuid, user_id.0)
-                                                        .await;
-                                                }
-                                                7 => {
-                                                    db_client
-                                                        .remove_not_available(uuid, user_id.0)
-                                                        .await;
-                                                }
-                                                _ => {}
-                                            }
-                                            util::update_embeds(uuid, &ctx, &db_client).await;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+        if let Err(why) = handle_reaction_remove(&ctx, &removed_reaction).await {
+            report_reaction_error(&ctx, &removed_reaction, why).await;
         }
     }
 
@@ -186,28 +106,158 @@ impl EventHandler for Handler {
                 println!("Failed to defer command: {why}");
             }
             match command.data.name.as_str() {
-                "war" => {
-                    if let Some(msg) = handle_war_command(&mut ctx, &command).await {
-                        edit_response_content(&ctx, msg, &command).await;
-                    }
-                }
-                "register" => {
-                    if let Some(msg) = handle_register_command(&mut ctx, &command).await {
-                        edit_response_content(&ctx, msg, &command).await;
+                "war" => match handle_war_command(&mut ctx, &command).await {
+                    Ok(Some(msg)) => edit_response_content(&ctx, msg, &command).await,
+                    Ok(None) => {}
+                    Err(why) => edit_response_content(&ctx, why, &command).await,
+                },
+                "register" => match handle_register_command(&mut ctx, &command).await {
+                    Ok(Some(msg)) => edit_response_content(&ctx, msg, &command).await,
+                    Ok(None) => {}
+                    Err(why) => edit_response_content(&ctx, why, &command).await,
+                },
+                "warstats" => match handle_war_stat_command(&mut ctx, &command).await {
+                    Ok(embed) => edit_response_embed(&ctx, embed, &command).await,
+                    Err(why) => edit_response_content(&ctx, why, &command).await,
+                },
+                _ => {}
+            }
+        } else if let Interaction::MessageComponent(component) = interaction {
+            if let Err(why) = component
+                .create_interaction_response(&ctx, |r| {
+                    r.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                        .interaction_response_data(|f| f.ephemeral(true))
+                })
+                .await
+            {
+                println!("Failed to defer component interaction: {why}");
+            }
+            match handle_signup_component(&ctx, &component).await {
+                Ok(msg) => edit_component_response(&ctx, msg, &component).await,
+                Err(why) => edit_component_response(&ctx, why, &component).await,
+            }
+        }
+    }
+}
+
+async fn handle_reaction_add(ctx: &Context, add_reaction: &Reaction) -> Result<()> {
+    if !add_reaction.user(ctx).await?.bot {
+        if let Some(guild_id) = add_reaction.guild_id {
+            if let Some(user_id) = add_reaction.user_id {
+                if let ReactionType::Unicode(emoji) = &add_reaction.emoji {
+                    if let Some(role) = RosterRole::from_emoji(emoji) {
+                        let db_client = ctx.data.read().await.get::<DBHandler>().unwrap().clone();
+                        if let Some(ac) = db_client
+                            .get_alert_connector_by_message(WarMessage::new(
+                                guild_id.0,
+                                add_reaction.channel_id.0,
+                                add_reaction.message_id.0,
+                            ))
+                            .await?
+                        {
+                            if let Ok(uuid) = Uuid::from_str(&ac.code) {
+                                if !ac.is_open()
+                                    || !add_reaction.message(ctx).await?.components.is_empty()
+                                {
+                                    return Ok(());
+                                }
+                                if let Some(reason) = util::check_requirements(
+                                    &ac,
+                                    user_id.0,
+                                    role,
+                                    db_client.as_ref(),
+                                )
+                                .await?
+                                {
+                                    util::remove_user_reaction(ctx, &ac, user_id.0, role).await;
+                                    util::notify_user(ctx, user_id.0, &reason).await;
+                                    return Ok(());
+                                }
+                                let change = db_client.signup(uuid, user_id.0, role, None).await?;
+                                if let Some(previous) = change.previous {
+                                    util::remove_user_reaction(ctx, &ac, user_id.0, previous).await;
+                                }
+                                if let Some(position) = change.waitlisted {
+                                    util::notify_user(
+                                        ctx,
+                                        user_id.0,
+                                        &format!(
+                                            "{} is full on **{}**, you are #{} on its waitlist. \
+                                            You will be moved in when a spot opens up.",
+                                            role.get_name(),
+                                            ac.get_title(),
+                                            position
+                                        ),
+                                    )
+                                    .await;
+                                }
+                                util::notify_promotions(ctx, &ac, &change.promoted).await;
+                                util::prompt_registration(ctx, user_id.0, db_client.as_ref())
+                                    .await?;
+                                util::update_embeds(uuid, ctx, db_client.as_ref()).await?;
+                            }
+                        }
                     }
                 }
-                "warstats" => {
-                    let result = handle_war_stat_command(&mut ctx, &command).await;
-                    if let Ok(embed) = result {
-                        edit_response_embed(&ctx, embed, &command).await;
-                    } else if let Err(why) = result {
-                        edit_response_content(&ctx, why, &command).await;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn handle_reaction_remove(ctx: &Context, removed_reaction: &Reaction) -> Result<()> {
+    if !removed_reaction.user(ctx).await?.bot {
+        if let Some(guild_id) = removed_reaction.guild_id {
+            if let Some(user_id) = removed_reaction.user_id {
+                if let ReactionType::Unicode(emoji) = &removed_reaction.emoji {
+                    if let Some(role) = RosterRole::from_emoji(emoji) {
+                        let db_client = ctx.data.read().await.get::<DBHandler>().unwrap().clone();
+                        if let Some(ac) = db_client
+                            .get_alert_connector_by_message(WarMessage::new(
+                                guild_id.0,
+                                removed_reaction.channel_id.0,
+                                removed_reaction.message_id.0,
+                            ))
+                            .await?
+                        {
+                            if let Ok(uuid) = Uuid::from_str(&ac.code) {
+                                if !ac.is_open()
+                                    || !removed_reaction.message(ctx).await?.components.is_empty()
+                                    || !ac.get_users().contains(&user_id.0)
+                                {
+                                    return Ok(());
+                                }
+                                let change = db_client.withdraw(uuid, user_id.0, role).await?;
+                                util::notify_promotions(ctx, &ac, &change.promoted).await;
+                                util::update_embeds(uuid, ctx, db_client.as_ref()).await?;
+                            }
+                        }
                     }
                 }
-                _ => {}
             }
         }
     }
+    Ok(())
+}
+
+/// Reactions have no interaction to reply to, so failures are sent to the user as a DM.
+async fn report_reaction_error(ctx: &Context, reaction: &Reaction, why: Error) {
+    println!("Failed to handle reaction: {}", why);
+    if let Some(user_id) = reaction.user_id {
+        let result = match user_id.create_dm_channel(ctx).await {
+            Ok(channel) => channel
+                .say(
+                    ctx,
+                    format!("Your war signup could not be updated. {}", why),
+                )
+                .await
+                .map(|_| ()),
+            Err(why) => Err(why),
+        };
+        if let Err(why) = result {
+            println!("Failed to notify user about reaction error: {}", why);
+        }
+    }
 }
 
 async fn edit_response_content(
@@ -236,42 +286,107 @@ async fn edit_response_embed(
     }
 }
 
+async fn edit_component_response(
+    ctx: &Context,
+    msg: impl ToString,
+    component: &MessageComponentInteraction,
+) {
+    if let Err(why) = component
+        .edit_original_interaction_response(&ctx.http, |response| response.content(msg))
+        .await
+    {
+        println!("Failed to respond to component interaction: {}", why);
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let token =
         env::var("DISCORD_TOKEN").expect("Failed to find token in env. Set it to DISCORD_TOKEN");
-    let db_string = env::var("MONGO_CON_STR")
-        .expect("Failed to find MongoDB connection string. Set it to MONGO_CON_STR");
+    let db_backend = env::var("DB_BACKEND").unwrap_or_else(|_| "mongo".to_string());
+    let signup_mode = match env::var("SIGNUP_MODE").as_deref() {
+        Ok("reactions") => SignupMode::Reactions,
+        Ok("buttons") | Err(_) => SignupMode::Buttons,
+        Ok(mode) => panic!("Unknown SIGNUP_MODE '{mode}'. Use 'buttons' or 'reactions'."),
+    };
 
     let intents = GatewayIntents::non_privileged()
         | GatewayIntents::GUILD_MEMBERS
         | GatewayIntents::MESSAGE_CONTENT;
 
     let mut client = Client::builder(token, intents)
-        .event_handler(Handler)
+        .event_handler(Handler {
+            scheduler_started: AtomicBool::new(false),
+        })
         .await
         .expect("Failed to create client.");
 
     {
-        let mut client_options = ClientOptions::parse(db_string)
-            .await
-            .expect("Failed to parse DB connection string");
-        client_options.app_name = Some("WarHelper".to_string());
+        let db_client: Arc<dyn DBManager> = match db_backend.as_str() {
+            "mongo" => Arc::new(connect_mongo().await),
+            "sqlite" => {
+                let path = env::var("SQLITE_PATH").unwrap_or_else(|_| "warhelper.db".to_string());
+                let dry_run = env::var("MIGRATION_DRY_RUN").is_ok();
+                let db = SqliteDB::open(path, dry_run).expect("Failed to open SQLite database");
+                if dry_run {
+                    println!("MIGRATION_DRY_RUN is set, exiting without starting the bot.");
+                    std::process::exit(0);
+                }
+                Arc::new(db)
+            }
+            "memory" => {
+                println!("Using in-memory storage. No data will be persisted.");
+                Arc::new(MemoryDB::new())
+            }
+            _ => panic!("Unknown DB_BACKEND '{db_backend}'. Use 'mongo', 'sqlite' or 'memory'."),
+        };
 
-        let db_client = mongodb::Client::with_options(client_options)
-            .expect("Failed to create db client with specified connection string and name");
+        if let Err(why) = db_client.create_indexes().await {
+            println!("Failed to create database indexes: {}", why);
+        }
 
-        db_client
-            .database("warhelperDB")
-            .run_command(doc! {"ping": 1}, None)
-            .await
-            .expect("Failed to ping DB");
+        match util::repair_roster_consistency(db_client.as_ref()).await {
+            Ok(0) => {}
+            Ok(repaired) => println!("Repaired {} roster entries with multiple roles", repaired),
+            Err(why) => println!("Failed to check roster consistency: {}", why),
+        }
 
         let mut data = client.data.write().await;
         data.insert::<DBHandler>(db_client);
+        data.insert::<SignupModeKey>(signup_mode);
     }
 
     if let Err(why) = client.start().await {
         println!("An error occurred while running the client: {}", why);
     }
 }
+
+async fn connect_mongo() -> mongodb::Client {
+    let db_string = env::var("MONGO_CON_STR")
+        .expect("Failed to find MongoDB connection string. Set it to MONGO_CON_STR");
+
+    let mut client_options = ClientOptions::parse(db_string)
+        .await
+        .expect("Failed to parse DB connection string");
+    client_options.app_name = Some("WarHelper".to_string());
+
+    let db_client = mongodb::Client::with_options(client_options)
+        .expect("Failed to create db client with specified connection string and name");
+
+    db_client
+        .database("warhelperDB")
+        .run_command(doc! {"ping": 1}, None)
+        .await
+        .expect("Failed to ping DB");
+
+    let dry_run = env::var("MIGRATION_DRY_RUN").is_ok();
+    if let Err(why) = migration::run_migrations(&db_client, dry_run).await {
+        panic!("Failed to migrate the database: {}", why);
+    }
+    if dry_run {
+        println!("MIGRATION_DRY_RUN is set, exiting without starting the bot.");
+        std::process::exit(0);
+    }
+
+    db_client
+}
@@ -0,0 +1,539 @@
+use crate::alert_connector::RosterChange;
+use crate::alert_status::AlertStatus;
+use crate::error::{Error, Result};
+use crate::group_assignment::GroupAssignment;
+use crate::guild_settings::GuildSettings;
+use crate::roster_order::RosterOrder;
+use crate::roster_role::RosterRole;
+use crate::trade_skill::TradeSkill;
+use crate::user_data::UserData;
+use crate::war_message::WarMessage;
+use crate::weapon::{Weapon, WEAPONS};
+use crate::{async_trait, AlertConnector, DBManager};
+use chrono_tz::Tz;
+use serenity::model::id::RoleId;
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+use uuid::Uuid;
+
+/// A [`DBManager`] that keeps everything in process memory.
+///
+/// Nothing is persisted, so all alerts, user data and permissions are lost when the bot stops.
+/// Useful for running the bot locally without a MongoDB instance.
+#[derive(Default)]
+pub struct MemoryDB {
+    alert_connectors: RwLock<HashMap<String, AlertConnector>>,
+    /// Alert code of every posted message.
+    war_messages: RwLock<HashMap<WarMessage, String>>,
+    user_data: RwLock<HashMap<u64, UserData>>,
+    permissions: RwLock<HashMap<u64, HashSet<u64>>>,
+    guild_settings: RwLock<HashMap<u64, GuildSettings>>,
+    reminders_sent: RwLock<HashSet<(String, i64)>>,
+    register_prompts_sent: RwLock<HashSet<u64>>,
+}
+
+impl MemoryDB {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn update_alert_connector(
+        &self,
+        uuid: Uuid,
+        f: impl FnOnce(&mut AlertConnector),
+    ) -> Result<()> {
+        if let Some(ac) = self
+            .alert_connectors
+            .write()
+            .map_err(|_| Error::LockPoisoned)?
+            .get_mut(&uuid.to_string())
+        {
+            f(ac);
+        }
+        Ok(())
+    }
+
+    fn index_war_message(&self, message: WarMessage, uuid: Uuid) -> Result<()> {
+        self.war_messages
+            .write()
+            .map_err(|_| Error::LockPoisoned)?
+            .insert(message, uuid.to_string());
+        Ok(())
+    }
+
+    fn update_user_data(&self, user_id: u64, f: impl FnOnce(&mut UserData)) -> Result<()> {
+        f(self
+            .user_data
+            .write()
+            .map_err(|_| Error::LockPoisoned)?
+            .entry(user_id)
+            .or_default());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DBManager for MemoryDB {
+    async fn create_indexes(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn get_alert_connector(&self, uuid: Uuid) -> Result<Option<AlertConnector>> {
+        Ok(self
+            .alert_connectors
+            .read()
+            .map_err(|_| Error::LockPoisoned)?
+            .get(&uuid.to_string())
+            .cloned())
+    }
+
+    async fn get_alert_connectors(&self) -> Result<Vec<AlertConnector>> {
+        Ok(self
+            .alert_connectors
+            .read()
+            .map_err(|_| Error::LockPoisoned)?
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    async fn get_alert_connector_by_message(
+        &self,
+        message: WarMessage,
+    ) -> Result<Option<AlertConnector>> {
+        let code = self
+            .war_messages
+            .read()
+            .map_err(|_| Error::LockPoisoned)?
+            .get(&message)
+            .cloned();
+        if let Some(code) = code {
+            Ok(self
+                .alert_connectors
+                .read()
+                .map_err(|_| Error::LockPoisoned)?
+                .get(&code)
+                .cloned())
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn get_alert_connectors_with_user_id(&self, user_id: u64) -> Result<Vec<AlertConnector>> {
+        Ok(self
+            .get_alert_connectors()
+            .await?
+            .into_iter()
+            .filter(|ac| ac.status != AlertStatus::Cancelled && ac.get_users().contains(&user_id))
+            .collect())
+    }
+
+    async fn get_upcoming_alert_connectors_with_user_id(
+        &self,
+        user_id: u64,
+        since: i64,
+    ) -> Result<Vec<AlertConnector>> {
+        Ok(self
+            .get_alert_connectors_with_user_id(user_id)
+            .await?
+            .into_iter()
+            .filter(|ac| {
+                ac.status != AlertStatus::Archived
+                    && ac.starts_at.is_some_and(|starts_at| starts_at >= since)
+            })
+            .collect())
+    }
+
+    async fn get_user_data(&self, user_id: u64) -> Result<Option<UserData>> {
+        Ok(self
+            .user_data
+            .read()
+            .map_err(|_| Error::LockPoisoned)?
+            .get(&user_id)
+            .cloned())
+    }
+
+    async fn has_permission(&self, guild_id: u64, roles: &[RoleId]) -> Result<bool> {
+        if let Some(perms) = self
+            .permissions
+            .read()
+            .map_err(|_| Error::LockPoisoned)?
+            .get(&guild_id)
+        {
+            Ok(roles.iter().any(|r| perms.contains(&r.0)))
+        } else {
+            Ok(false)
+        }
+    }
+
+    async fn add_permission(&self, guild_id: u64, role_id: u64) -> Result<()> {
+        self.permissions
+            .write()
+            .map_err(|_| Error::LockPoisoned)?
+            .entry(guild_id)
+            .or_default()
+            .insert(role_id);
+        Ok(())
+    }
+
+    async fn remove_permission(&self, guild_id: u64, role_id: u64) -> Result<()> {
+        if let Some(perms) = self
+            .permissions
+            .write()
+            .map_err(|_| Error::LockPoisoned)?
+            .get_mut(&guild_id)
+        {
+            perms.remove(&role_id);
+        }
+        Ok(())
+    }
+
+    async fn get_guild_settings(&self, guild_id: u64) -> Result<GuildSettings> {
+        Ok(self
+            .guild_settings
+            .read()
+            .map_err(|_| Error::LockPoisoned)?
+            .get(&guild_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn set_guild_time_zone(&self, guild_id: u64, time_zone: Tz) -> Result<()> {
+        self.guild_settings
+            .write()
+            .map_err(|_| Error::LockPoisoned)?
+            .entry(guild_id)
+            .or_default()
+            .time_zone = time_zone.name().to_string();
+        Ok(())
+    }
+
+    async fn channel_contains_war_message(
+        &self,
+        guild_id: u64,
+        channel_id: u64,
+        uuid: Uuid,
+    ) -> Result<bool> {
+        if let Some(ac) = self.get_alert_connector(uuid).await? {
+            Ok(ac.channel_contains_war_message(guild_id, channel_id))
+        } else {
+            Ok(false)
+        }
+    }
+
+    async fn add_war_message(&self, uuid: Uuid, message: WarMessage) -> Result<()> {
+        self.update_alert_connector(uuid, |ac| {
+            if !ac.war_messages.contains(&message) {
+                ac.war_messages.push(message);
+            }
+        })?;
+        self.index_war_message(message, uuid)
+    }
+
+    async fn create_alert_connector(&self, ac: &AlertConnector) -> Result<()> {
+        self.alert_connectors
+            .write()
+            .map_err(|_| Error::LockPoisoned)?
+            .insert(ac.code.clone(), ac.clone());
+        let mut war_messages = self.war_messages.write().map_err(|_| Error::LockPoisoned)?;
+        for &message in &ac.war_messages {
+            war_messages.insert(message, ac.code.clone());
+        }
+        Ok(())
+    }
+
+    async fn update_alert_details(&self, ac: &AlertConnector) -> Result<()> {
+        if let Some(stored) = self
+            .alert_connectors
+            .write()
+            .map_err(|_| Error::LockPoisoned)?
+            .get_mut(&ac.code)
+        {
+            stored.date = ac.date.clone();
+            stored.time = ac.time.clone();
+            stored.territory = ac.territory.clone();
+            stored.title = ac.title.clone();
+            stored.description = ac.description.clone();
+            if stored.starts_at != ac.starts_at {
+                stored.starts_at = ac.starts_at;
+                self.reminders_sent
+                    .write()
+                    .map_err(|_| Error::LockPoisoned)?
+                    .retain(|(code, _)| *code != ac.code);
+            }
+        }
+        Ok(())
+    }
+
+    async fn set_alert_status(&self, uuid: Uuid, status: AlertStatus) -> Result<()> {
+        self.update_alert_connector(uuid, |ac| ac.status = status)
+    }
+
+    async fn set_roster_order(&self, uuid: Uuid, order: RosterOrder) -> Result<()> {
+        self.update_alert_connector(uuid, |ac| ac.roster_order = order)
+    }
+
+    async fn set_group_assignment(&self, uuid: Uuid, assignment: GroupAssignment) -> Result<()> {
+        self.update_alert_connector(uuid, |ac| {
+            ac.assignments
+                .retain(|other| other.user_id != assignment.user_id);
+            ac.assignments.push(assignment);
+        })
+    }
+
+    async fn set_army_message(&self, uuid: Uuid, message: WarMessage) -> Result<()> {
+        self.update_alert_connector(uuid, |ac| {
+            ac.army_messages.retain(|army_message| {
+                army_message.get_guild_id() != message.get_guild_id()
+                    || army_message.get_channel_id() != message.get_channel_id()
+            });
+            ac.army_messages.push(message);
+        })
+    }
+
+    async fn get_due_alert_connectors(
+        &self,
+        status: AlertStatus,
+        before: i64,
+    ) -> Result<Vec<AlertConnector>> {
+        Ok(self
+            .get_alert_connectors()
+            .await?
+            .into_iter()
+            .filter(|ac| {
+                ac.status == status && ac.starts_at.is_some_and(|starts_at| starts_at < before)
+            })
+            .collect())
+    }
+
+    async fn mark_reminder_sent(&self, uuid: Uuid, offset: i64) -> Result<bool> {
+        Ok(self
+            .reminders_sent
+            .write()
+            .map_err(|_| Error::LockPoisoned)?
+            .insert((uuid.to_string(), offset)))
+    }
+
+    async fn mark_register_prompt_sent(&self, user_id: u64) -> Result<bool> {
+        Ok(self
+            .register_prompts_sent
+            .write()
+            .map_err(|_| Error::LockPoisoned)?
+            .insert(user_id))
+    }
+
+    async fn update_main_hand(&self, user_id: u64, weapon: Weapon) -> Result<()> {
+        self.update_user_data(user_id, |data| data.main_hand = Some(weapon))
+    }
+
+    async fn update_secondary(&self, user_id: u64, weapon: Weapon) -> Result<()> {
+        self.update_user_data(user_id, |data| data.secondary = Some(weapon))
+    }
+
+    async fn update_level(&self, user_id: u64, level: u8) -> Result<()> {
+        self.update_user_data(user_id, |data| data.level = level)
+    }
+
+    async fn update_gear_score(&self, user_id: u64, gear_score: u16) -> Result<()> {
+        self.update_user_data(user_id, |data| data.gear_score = gear_score)
+    }
+
+    async fn update_trade_skill(&self, user_id: u64, level: u8, skill: TradeSkill) -> Result<()> {
+        self.update_user_data(user_id, |data| {
+            data.trade_skills.insert(skill, level);
+        })
+    }
+
+    async fn update_weapon_level(&self, user_id: u64, level: u8, weapon: Weapon) -> Result<()> {
+        self.update_user_data(user_id, |data| {
+            data.weapons.insert(weapon, level);
+        })
+    }
+
+    async fn update_username(&self, user_id: u64, username: &str) -> Result<()> {
+        self.update_user_data(user_id, |data| data.username = username.to_string())
+    }
+
+    async fn signup(
+        &self,
+        uuid: Uuid,
+        user_id: u64,
+        role: RosterRole,
+        added_by: Option<u64>,
+    ) -> Result<RosterChange> {
+        let mut change = RosterChange::default();
+        self.update_alert_connector(uuid, |ac| change = ac.signup(user_id, role, added_by))?;
+        Ok(change)
+    }
+
+    async fn withdraw(&self, uuid: Uuid, user_id: u64, role: RosterRole) -> Result<RosterChange> {
+        let mut change = RosterChange::default();
+        self.update_alert_connector(uuid, |ac| change = ac.withdraw(user_id, role))?;
+        Ok(change)
+    }
+
+    async fn remove_duplicate_entries(&self, uuid: Uuid) -> Result<()> {
+        self.update_alert_connector(uuid, AlertConnector::remove_duplicate_entries)
+    }
+
+    async fn get_user_data_stats(
+        &self,
+    ) -> Result<(u32, u32, HashMap<Weapon, u32>, HashMap<Weapon, u32>)> {
+        let mut total_level = 0;
+        let mut level_count = 0;
+        let mut total_gear_score = 0;
+        let mut gear_score_count = 0;
+        let mut main_hand_count = HashMap::new();
+        let mut secondary_count = HashMap::new();
+        for weapon in WEAPONS {
+            main_hand_count.insert(weapon, 0);
+            secondary_count.insert(weapon, 0);
+        }
+        for user_data in self
+            .user_data
+            .read()
+            .map_err(|_| Error::LockPoisoned)?
+            .values()
+        {
+            if user_data.level > 1 {
+                total_level += user_data.level as u32;
+                level_count += 1;
+            }
+            if user_data.gear_score > 0 {
+                total_gear_score += user_data.gear_score as u32;
+                gear_score_count += 1;
+            }
+            if let Some(weapon) = user_data.main_hand {
+                *main_hand_count.entry(weapon).or_insert(0) += 1;
+            }
+            if let Some(weapon) = user_data.secondary {
+                *secondary_count.entry(weapon).or_insert(0) += 1;
+            }
+        }
+        if gear_score_count == 0 {
+            gear_score_count = 1;
+        }
+        if level_count == 0 {
+            level_count = 1;
+        }
+        Ok((
+            total_level / level_count,
+            total_gear_score / gear_score_count,
+            main_hand_count,
+            secondary_count,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn round_trips_alerts_user_data_and_permissions() {
+        let db = MemoryDB::new();
+        let uuid = Uuid::new_v5(&Uuid::NAMESPACE_OID, b"round trip");
+        db.create_alert_connector(&AlertConnector {
+            code: uuid.to_string(),
+            date: "Sat 1. Oct".to_string(),
+            time: "08:00pm".to_string(),
+            territory: "everfall".to_string(),
+            starts_at: Some(1_000),
+            war_messages: vec![WarMessage::new(1, 2, 3)],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let ac = db.get_alert_connector(uuid).await.unwrap().unwrap();
+        assert_eq!(ac.code, uuid.to_string());
+        assert_eq!(ac.territory, "everfall");
+        assert_eq!(ac.starts_at, Some(1_000));
+        let by_message = db
+            .get_alert_connector_by_message(WarMessage::new(1, 2, 3))
+            .await
+            .unwrap();
+        assert_eq!(by_message.map(|ac| ac.code), Some(uuid.to_string()));
+
+        db.signup(uuid, 10, RosterRole::Tank, None).await.unwrap();
+        let ac = db.get_alert_connector(uuid).await.unwrap().unwrap();
+        assert!(ac.has_role(10, RosterRole::Tank));
+        assert_eq!(
+            db.get_alert_connectors_with_user_id(10)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(db
+            .get_upcoming_alert_connectors_with_user_id(10, 2_000)
+            .await
+            .unwrap()
+            .is_empty());
+
+        db.update_username(10, "Shieldwall").await.unwrap();
+        db.update_gear_score(10, 600).await.unwrap();
+        db.update_main_hand(10, Weapon::SwordAndShield)
+            .await
+            .unwrap();
+        let user_data = db.get_user_data(10).await.unwrap().unwrap();
+        assert_eq!(user_data.username, "Shieldwall");
+        assert_eq!(user_data.gear_score, 600);
+        assert_eq!(user_data.main_hand, Some(Weapon::SwordAndShield));
+        assert!(db.get_user_data(11).await.unwrap().is_none());
+
+        db.add_permission(1, 5).await.unwrap();
+        assert!(db.has_permission(1, &[RoleId(5)]).await.unwrap());
+        assert!(!db.has_permission(1, &[RoleId(6)]).await.unwrap());
+        db.remove_permission(1, 5).await.unwrap();
+        assert!(!db.has_permission(1, &[RoleId(5)]).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn upcoming_alerts_leave_out_past_ones_and_those_without_a_start() {
+        let db = MemoryDB::new();
+        let upcoming = Uuid::new_v5(&Uuid::NAMESPACE_OID, b"upcoming");
+        let past = Uuid::new_v5(&Uuid::NAMESPACE_OID, b"past");
+        let legacy = Uuid::new_v5(&Uuid::NAMESPACE_OID, b"legacy");
+        for (uuid, starts_at) in [(upcoming, Some(3_000)), (past, Some(1_000)), (legacy, None)] {
+            db.create_alert_connector(&AlertConnector {
+                code: uuid.to_string(),
+                starts_at,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+            db.signup(uuid, 10, RosterRole::Mdps, None).await.unwrap();
+        }
+
+        let found = db
+            .get_upcoming_alert_connectors_with_user_id(10, 2_000)
+            .await
+            .unwrap();
+        assert_eq!(
+            found.into_iter().map(|ac| ac.code).collect::<Vec<_>>(),
+            vec![upcoming.to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn rescheduling_clears_sent_reminders() {
+        let db = MemoryDB::new();
+        let uuid = Uuid::new_v5(&Uuid::NAMESPACE_OID, b"rescheduled");
+        let mut ac = AlertConnector {
+            code: uuid.to_string(),
+            starts_at: Some(1_000),
+            ..Default::default()
+        };
+        db.create_alert_connector(&ac).await.unwrap();
+        assert!(db.mark_reminder_sent(uuid, 60).await.unwrap());
+
+        ac.title = "Renamed".to_string();
+        db.update_alert_details(&ac).await.unwrap();
+        assert!(!db.mark_reminder_sent(uuid, 60).await.unwrap());
+
+        ac.starts_at = Some(100_000);
+        db.update_alert_details(&ac).await.unwrap();
+        assert!(db.mark_reminder_sent(uuid, 60).await.unwrap());
+    }
+}
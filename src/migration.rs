@@ -0,0 +1,282 @@
+use crate::error::Result;
+use crate::roster_role::ROSTER_ROLES;
+use futures::future::BoxFuture;
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use mongodb::options::UpdateOptions;
+
+/// A single schema change. Every migration must be safe to run again on data it already changed.
+struct Migration {
+    version: u32,
+    description: &'static str,
+    /// Applies the change, or only counts the affected documents when `dry_run` is set.
+    /// Returns the number of documents that were or would be changed.
+    run: for<'a> fn(&'a mongodb::Client, bool) -> BoxFuture<'a, Result<u64>>,
+}
+
+/// All migrations in the order they are applied. Append new ones with the next version.
+const MIGRATIONS: [Migration; 6] = [
+    Migration {
+        version: 1,
+        description: "Key UserData and Permissions documents by _id",
+        run: key_documents_by_id,
+    },
+    Migration {
+        version: 2,
+        description: "Add missing roster lists to AlertConnectors",
+        run: add_missing_roster_lists,
+    },
+    Migration {
+        version: 3,
+        description: "Mark existing AlertConnectors as open",
+        run: add_missing_status,
+    },
+    Migration {
+        version: 4,
+        description: "Add a roster revision to AlertConnectors",
+        run: add_missing_revision,
+    },
+    Migration {
+        version: 5,
+        description: "Turn roster user IDs into roster entries",
+        run: convert_roster_entries,
+    },
+    Migration {
+        version: 6,
+        description: "Archive AlertConnectors without a start time",
+        run: archive_alerts_without_start,
+    },
+];
+
+/// Applies every migration newer than the version stored in `warhelperDB`, recording the new
+/// version after each one. With `dry_run` set nothing is written, only reported.
+pub async fn run_migrations(client: &mongodb::Client, dry_run: bool) -> Result<()> {
+    let current = get_schema_version(client).await?;
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let changed = (migration.run)(client, dry_run).await?;
+        if dry_run {
+            println!(
+                "Migration {} ({}) would change {} documents",
+                migration.version, migration.description, changed
+            );
+        } else {
+            set_schema_version(client, migration.version).await?;
+            println!(
+                "Applied migration {} ({}), changed {} documents",
+                migration.version, migration.description, changed
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn get_schema_version(client: &mongodb::Client) -> Result<u32> {
+    Ok(client
+        .database("warhelperDB")
+        .collection::<Document>("SchemaVersion")
+        .find_one(doc! {"_id": "schema"}, None)
+        .await?
+        .and_then(|document| document.get_i64("version").ok())
+        .unwrap_or(0) as u32)
+}
+
+async fn set_schema_version(client: &mongodb::Client, version: u32) -> Result<()> {
+    client
+        .database("warhelperDB")
+        .collection::<Document>("SchemaVersion")
+        .update_one(
+            doc! {"_id": "schema"},
+            doc! {"$set": {"version": version as i64}},
+            upsert(),
+        )
+        .await?;
+    Ok(())
+}
+
+fn upsert() -> UpdateOptions {
+    let mut options = UpdateOptions::default();
+    options.upsert = Some(true);
+    options
+}
+
+/// Moves `UserData` documents stored as `{ "<user_id>": {...} }` and `Permissions` documents
+/// stored as `{ "<guild_id>": [...] }` to documents keyed by `_id`.
+fn key_documents_by_id(client: &mongodb::Client, dry_run: bool) -> BoxFuture<'_, Result<u64>> {
+    Box::pin(async move {
+        let database = client.database("warhelperDB");
+        let legacy = doc! {"_id": {"$type": "objectId"}};
+
+        let user_data = database.collection::<Document>("UserData");
+        let permissions = database.collection::<Document>("Permissions");
+        if dry_run {
+            return Ok(user_data.count_documents(legacy.clone(), None).await?
+                + permissions.count_documents(legacy, None).await?);
+        }
+
+        let mut migrated = 0;
+        let mut documents = user_data.find(legacy.clone(), None).await?;
+        while let Some(document) = documents.try_next().await? {
+            for (key, value) in document.iter().filter(|(key, _)| *key != "_id") {
+                if let (Ok(user_id), Some(inner)) = (key.parse::<u64>(), value.as_document()) {
+                    user_data
+                        .update_one(
+                            doc! {"_id": user_id as i64},
+                            doc! {"$setOnInsert": inner.clone()},
+                            upsert(),
+                        )
+                        .await?;
+                }
+            }
+            user_data
+                .delete_one(doc! {"_id": document.get("_id")}, None)
+                .await?;
+            migrated += 1;
+        }
+
+        let mut documents = permissions.find(legacy, None).await?;
+        while let Some(document) = documents.try_next().await? {
+            for (key, value) in document.iter().filter(|(key, _)| *key != "_id") {
+                if let (Ok(guild_id), Some(roles)) = (key.parse::<u64>(), value.as_array()) {
+                    permissions
+                        .update_one(
+                            doc! {"_id": guild_id as i64},
+                            doc! {"$addToSet": {"roles": {"$each": roles.clone()}}},
+                            upsert(),
+                        )
+                        .await?;
+                }
+            }
+            permissions
+                .delete_one(doc! {"_id": document.get("_id")}, None)
+                .await?;
+            migrated += 1;
+        }
+
+        Ok(migrated)
+    })
+}
+
+/// Alerts created before a role existed have no list for it, which makes roster queries on that
+/// field miss them.
+fn add_missing_roster_lists(client: &mongodb::Client, dry_run: bool) -> BoxFuture<'_, Result<u64>> {
+    Box::pin(async move {
+        let alert_connectors = client
+            .database("warhelperDB")
+            .collection::<Document>("AlertConnectors");
+        let mut changed = 0;
+        for role in ROSTER_ROLES {
+            let filter = doc! {role.get_field(): {"$exists": false}};
+            changed += if dry_run {
+                alert_connectors.count_documents(filter, None).await?
+            } else {
+                alert_connectors
+                    .update_many(filter, doc! {"$set": {role.get_field(): []}}, None)
+                    .await?
+                    .modified_count
+            };
+        }
+        Ok(changed)
+    })
+}
+
+/// The scheduler only looks at alerts with a status, so older alerts get the default one.
+fn add_missing_status(client: &mongodb::Client, dry_run: bool) -> BoxFuture<'_, Result<u64>> {
+    Box::pin(async move {
+        let alert_connectors = client
+            .database("warhelperDB")
+            .collection::<Document>("AlertConnectors");
+        let filter = doc! {"status": {"$exists": false}};
+        Ok(if dry_run {
+            alert_connectors.count_documents(filter, None).await?
+        } else {
+            alert_connectors
+                .update_many(filter, doc! {"$set": {"status": "open"}}, None)
+                .await?
+                .modified_count
+        })
+    })
+}
+
+/// Alerts from before the start time was stored can't be closed or archived on schedule, and
+/// would count as upcoming forever.
+fn archive_alerts_without_start(
+    client: &mongodb::Client,
+    dry_run: bool,
+) -> BoxFuture<'_, Result<u64>> {
+    Box::pin(async move {
+        let alert_connectors = client
+            .database("warhelperDB")
+            .collection::<Document>("AlertConnectors");
+        let filter = doc! {
+            "$or": [{"startsAt": {"$exists": false}}, {"startsAt": null}],
+            "status": {"$ne": "archived"},
+        };
+        Ok(if dry_run {
+            alert_connectors.count_documents(filter, None).await?
+        } else {
+            alert_connectors
+                .update_many(filter, doc! {"$set": {"status": "archived"}}, None)
+                .await?
+                .modified_count
+        })
+    })
+}
+
+/// Roster updates only save when the revision they read is unchanged, which needs the field.
+fn add_missing_revision(client: &mongodb::Client, dry_run: bool) -> BoxFuture<'_, Result<u64>> {
+    Box::pin(async move {
+        let alert_connectors = client
+            .database("warhelperDB")
+            .collection::<Document>("AlertConnectors");
+        let filter = doc! {"revision": {"$exists": false}};
+        Ok(if dry_run {
+            alert_connectors.count_documents(filter, None).await?
+        } else {
+            alert_connectors
+                .update_many(filter, doc! {"$set": {"revision": 0_i64}}, None)
+                .await?
+                .modified_count
+        })
+    })
+}
+
+/// Roster lists used to hold plain user IDs. They become entries without a known signup time,
+/// which keeps them in their old order.
+fn convert_roster_entries(client: &mongodb::Client, dry_run: bool) -> BoxFuture<'_, Result<u64>> {
+    Box::pin(async move {
+        let alert_connectors = client
+            .database("warhelperDB")
+            .collection::<Document>("AlertConnectors");
+        let mut changed = 0;
+        for role in ROSTER_ROLES {
+            let field = role.get_field();
+            let filter = doc! {field: {"$type": "number"}};
+            changed += if dry_run {
+                alert_connectors.count_documents(filter, None).await?
+            } else {
+                alert_connectors
+                    .update_many(
+                        filter,
+                        vec![doc! {"$set": {field: {"$map": {
+                            "input": format!("${}", field),
+                            "as": "entry",
+                            "in": {"$cond": [
+                                {"$isNumber": "$$entry"},
+                                {
+                                    "userId": "$$entry",
+                                    "signedUpAt": 0_i64,
+                                    "joinedAt": 0_i64,
+                                    "history": []
+                                },
+                                "$$entry"
+                            ]}
+                        }}}}],
+                        None,
+                    )
+                    .await?
+                    .modified_count
+            };
+        }
+        Ok(changed)
+    })
+}
@@ -0,0 +1,107 @@
+use crate::user_data::UserData;
+use crate::weapon::Weapon;
+use serde::{Deserialize, Serialize};
+
+/// What members need to sign up for a combat role on an alert. Unset values are not checked.
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug, Serialize, Deserialize)]
+pub struct Requirements {
+    #[serde(rename = "minGearScore", default)]
+    pub min_gear_score: Option<u16>,
+    #[serde(rename = "minLevel", default)]
+    pub min_level: Option<u8>,
+    /// A weapon the member must have as main hand or secondary.
+    #[serde(rename = "requiredWeapon", default)]
+    pub required_weapon: Option<Weapon>,
+}
+
+impl Requirements {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The requirements as shown in the embed. Ex: "GS 500+, level 60+, Great Axe"
+    pub fn get_label(&self) -> String {
+        let mut parts = vec![];
+        if let Some(gear_score) = self.min_gear_score {
+            parts.push(format!("GS {}+", gear_score));
+        }
+        if let Some(level) = self.min_level {
+            parts.push(format!("level {}+", level));
+        }
+        if let Some(weapon) = self.required_weapon {
+            parts.push(weapon.get_label().to_string());
+        }
+        parts.join(", ")
+    }
+
+    /// Why `user_data` doesn't meet the requirements, or `None` if it does.
+    pub fn get_unmet(&self, user_data: &UserData) -> Option<String> {
+        if let Some(gear_score) = self.min_gear_score {
+            if user_data.gear_score < gear_score {
+                return Some(format!(
+                    "This alert needs a gear score of at least {}, yours is {}.",
+                    gear_score, user_data.gear_score
+                ));
+            }
+        }
+        if let Some(level) = self.min_level {
+            if user_data.level < level {
+                return Some(format!(
+                    "This alert needs level {} or higher, yours is {}.",
+                    level, user_data.level
+                ));
+            }
+        }
+        if let Some(weapon) = self.required_weapon {
+            if user_data.main_hand != Some(weapon) && user_data.secondary != Some(weapon) {
+                return Some(format!(
+                    "This alert needs a {} as your main hand or secondary weapon.",
+                    weapon.get_label()
+                ));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_unmet_reports_each_requirement() {
+        let requirements = Requirements {
+            min_gear_score: Some(500),
+            min_level: Some(60),
+            required_weapon: Some(Weapon::Rapier),
+        };
+        let mut user_data = UserData {
+            gear_score: 450,
+            level: 55,
+            ..Default::default()
+        };
+        assert_eq!(
+            requirements.get_unmet(&user_data).as_deref(),
+            Some("This alert needs a gear score of at least 500, yours is 450.")
+        );
+        user_data.gear_score = 500;
+        assert_eq!(
+            requirements.get_unmet(&user_data).as_deref(),
+            Some("This alert needs level 60 or higher, yours is 55.")
+        );
+        user_data.level = 60;
+        assert_eq!(
+            requirements.get_unmet(&user_data),
+            Some(format!(
+                "This alert needs a {} as your main hand or secondary weapon.",
+                Weapon::Rapier.get_label()
+            ))
+        );
+        user_data.secondary = Some(Weapon::Rapier);
+        assert_eq!(requirements.get_unmet(&user_data), None);
+        assert_eq!(
+            Requirements::default().get_unmet(&UserData::default()),
+            None
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use crate::roster_role::RosterRole;
+use serde::{Deserialize, Serialize};
+
+/// A member's spot in one of the roles of an alert.
+#[derive(Clone, Eq, PartialEq, Deserialize, Serialize)]
+pub struct RosterEntry {
+    #[serde(rename = "userId")]
+    pub user_id: u64,
+    /// When the user first responded to the alert, as a unix timestamp. Kept when they change
+    /// roles. 0 for signups from before it was stored.
+    #[serde(rename = "signedUpAt", default)]
+    pub signed_up_at: i64,
+    /// When the user moved into their current role.
+    #[serde(rename = "joinedAt", default)]
+    pub joined_at: i64,
+    /// The officer that signed the user up for them, if they didn't sign up themselves.
+    #[serde(rename = "addedBy", default)]
+    pub added_by: Option<u64>,
+    /// The roles the user held before the current one, oldest first.
+    #[serde(default)]
+    pub history: Vec<RoleChange>,
+}
+
+/// A role a member held earlier on the same alert.
+#[derive(Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+pub struct RoleChange {
+    pub role: RosterRole,
+    #[serde(rename = "joinedAt")]
+    pub joined_at: i64,
+}
+
+impl RosterEntry {
+    pub fn new(user_id: u64, signed_up_at: i64) -> Self {
+        Self {
+            user_id,
+            signed_up_at,
+            joined_at: signed_up_at,
+            added_by: None,
+            history: vec![],
+        }
+    }
+
+    /// Records that the user left `previous` for another role at `now`.
+    pub fn change_role(&mut self, previous: RosterRole, now: i64) {
+        self.history.push(RoleChange {
+            role: previous,
+            joined_at: self.joined_at,
+        });
+        self.joined_at = now;
+    }
+}
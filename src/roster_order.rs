@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+/// How the members of each role are listed in the alert embed.
+#[derive(Copy, Clone, Eq, PartialEq, Default, Hash, Serialize, Deserialize, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub enum RosterOrder {
+    /// First come first served, by the time members first responded.
+    #[default]
+    Signup,
+    /// Highest gear score first.
+    GearScore,
+}
+
+impl From<RosterOrder> for String {
+    fn from(order: RosterOrder) -> Self {
+        match order {
+            RosterOrder::Signup => "signup".to_string(),
+            RosterOrder::GearScore => "gearScore".to_string(),
+        }
+    }
+}
+
+impl TryFrom<String> for RosterOrder {
+    type Error = &'static str;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "signup" => Ok(RosterOrder::Signup),
+            "gearScore" => Ok(RosterOrder::GearScore),
+            _ => Err("Failed to parse roster order"),
+        }
+    }
+}
+
+impl RosterOrder {
+    pub fn get_label(&self) -> &'static str {
+        match self {
+            RosterOrder::Signup => "Signup order",
+            RosterOrder::GearScore => "Gear score",
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+
+pub const ROSTER_ROLES: [RosterRole; 8] = [
+    RosterRole::Tank,
+    RosterRole::Mdps,
+    RosterRole::Prdps,
+    RosterRole::Erdps,
+    RosterRole::Healer,
+    RosterRole::Artillery,
+    RosterRole::Tentative,
+    RosterRole::NotAvailable,
+];
+
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum RosterRole {
+    Tank,
+    Mdps,
+    Prdps,
+    Erdps,
+    Healer,
+    Artillery,
+    Tentative,
+    NotAvailable,
+}
+
+impl From<RosterRole> for String {
+    fn from(role: RosterRole) -> Self {
+        role.get_field().to_string()
+    }
+}
+
+impl TryFrom<String> for RosterRole {
+    type Error = &'static str;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        RosterRole::from_field(&value).ok_or("Failed to parse roster role")
+    }
+}
+
+impl RosterRole {
+    pub fn from_emoji(emoji: &str) -> Option<Self> {
+        ROSTER_ROLES
+            .into_iter()
+            .find(|role| role.get_emoji().to_string() == emoji)
+    }
+
+    pub fn from_field(field: &str) -> Option<Self> {
+        ROSTER_ROLES
+            .into_iter()
+            .find(|role| role.get_field() == field)
+    }
+
+    pub fn get_emoji(&self) -> char {
+        match self {
+            RosterRole::Tank => '🛡',
+            RosterRole::Mdps => '🗡',
+            RosterRole::Prdps => '🏹',
+            RosterRole::Erdps => '🪄',
+            RosterRole::Healer => '❤',
+            RosterRole::Artillery => '💥',
+            RosterRole::Tentative => '❓',
+            RosterRole::NotAvailable => '⛔',
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            RosterRole::Tank => "Tank",
+            RosterRole::Mdps => "MDPS",
+            RosterRole::Prdps => "Physical RDPS",
+            RosterRole::Erdps => "Elemental RDPS",
+            RosterRole::Healer => "Healer",
+            RosterRole::Artillery => "Artillery",
+            RosterRole::Tentative => "Tentative",
+            RosterRole::NotAvailable => "Not Available",
+        }
+    }
+
+    pub fn get_label(&self) -> &'static str {
+        match self {
+            RosterRole::Tank => ":shield: TANK :shield:",
+            RosterRole::Mdps => ":dagger: MDPS :dagger:",
+            RosterRole::Prdps => ":archery: Physical RDPS :archery:",
+            RosterRole::Erdps => ":magic_wand: Elemental RDPS :magic_wand:",
+            RosterRole::Healer => ":heart: Healer :heart:",
+            RosterRole::Artillery => ":boom: Artillery :boom:",
+            RosterRole::Tentative => ":question: Tentative :question:",
+            RosterRole::NotAvailable => ":no_entry: Not Available :no_entry:",
+        }
+    }
+
+    /// The name of the `AlertConnector` list this role is stored in.
+    pub fn get_field(&self) -> &'static str {
+        match self {
+            RosterRole::Tank => "tanks",
+            RosterRole::Mdps => "mdps",
+            RosterRole::Prdps => "prdps",
+            RosterRole::Erdps => "erdps",
+            RosterRole::Healer => "healers",
+            RosterRole::Artillery => "artillery",
+            RosterRole::Tentative => "tentative",
+            RosterRole::NotAvailable => "notAvailable",
+        }
+    }
+
+    /// Whether members in this role take part in the war, as opposed to just responding.
+    pub fn is_combat_role(&self) -> bool {
+        !matches!(self, RosterRole::Tentative | RosterRole::NotAvailable)
+    }
+}
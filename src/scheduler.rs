@@ -0,0 +1,171 @@
+use crate::alert_connector::AlertConnector;
+use crate::alert_status::AlertStatus;
+use crate::db_manager::DBManager;
+use crate::error::Result;
+use crate::roster_role::{RosterRole, ROSTER_ROLES};
+use crate::{util, DBHandler};
+use chrono::Utc;
+use serenity::prelude::Context;
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+use uuid::Uuid;
+
+const TICK: Duration = Duration::from_secs(60);
+
+/// Closes signups `SIGNUP_CLOSE_MINUTES` (default 15) before each war, sends the reminders set
+/// in `REMINDER_OFFSETS` and archives the alert `WAR_DURATION_MINUTES` (default 60) after it
+/// started. Runs until the bot stops.
+pub async fn run_scheduler(ctx: Context) {
+    let close_before = minutes_from_env("SIGNUP_CLOSE_MINUTES", 15);
+    let war_duration = minutes_from_env("WAR_DURATION_MINUTES", 60);
+    let reminder_offsets = reminder_offsets_from_env();
+    let db_client = ctx.data.read().await.get::<DBHandler>().unwrap().clone();
+
+    let mut interval = tokio::time::interval(TICK);
+    loop {
+        interval.tick().await;
+        let now = Utc::now().timestamp();
+        if let Err(why) = send_reminders(&ctx, &reminder_offsets, now, db_client.as_ref()).await {
+            println!("Failed to send reminders: {}", why);
+        }
+        if let Err(why) = advance_alerts(
+            &ctx,
+            AlertStatus::Open,
+            AlertStatus::Closed,
+            now + close_before,
+            db_client.as_ref(),
+        )
+        .await
+        {
+            println!("Failed to close signups: {}", why);
+        }
+        if let Err(why) = advance_alerts(
+            &ctx,
+            AlertStatus::Closed,
+            AlertStatus::Archived,
+            now - war_duration,
+            db_client.as_ref(),
+        )
+        .await
+        {
+            println!("Failed to archive alerts: {}", why);
+        }
+    }
+}
+
+/// Moves every alert in `from` that starts before `before` to `to` and updates its embeds.
+async fn advance_alerts(
+    ctx: &Context,
+    from: AlertStatus,
+    to: AlertStatus,
+    before: i64,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<()> {
+    for ac in db_client.get_due_alert_connectors(from, before).await? {
+        if let Ok(uuid) = Uuid::from_str(&ac.code) {
+            db_client.set_alert_status(uuid, to).await?;
+            util::update_embeds(uuid, ctx, db_client).await?;
+        }
+    }
+    Ok(())
+}
+
+/// DMs the combat roster of every alert that reached one of its reminder offsets, and asks
+/// tentative members to confirm. Offsets are in minutes before the start.
+async fn send_reminders(
+    ctx: &Context,
+    offsets: &[i64],
+    now: i64,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<()> {
+    let max_offset = match offsets.iter().max() {
+        Some(max_offset) => *max_offset,
+        None => return Ok(()),
+    };
+    for status in [AlertStatus::Open, AlertStatus::Closed] {
+        for ac in db_client
+            .get_due_alert_connectors(status, now + max_offset * 60)
+            .await?
+        {
+            if let (Ok(uuid), Some(starts_at)) = (Uuid::from_str(&ac.code), ac.starts_at) {
+                if starts_at <= now {
+                    continue;
+                }
+                // Only the closest due offset is sent, so a bot that was offline for a while
+                // doesn't send several reminders at once.
+                let due = offsets
+                    .iter()
+                    .filter(|&&offset| starts_at - offset * 60 <= now)
+                    .min();
+                if let Some(&offset) = due {
+                    for &missed in offsets.iter().filter(|&&missed| missed > offset) {
+                        db_client.mark_reminder_sent(uuid, missed).await?;
+                    }
+                    if db_client.mark_reminder_sent(uuid, offset).await? {
+                        remind_roster(ctx, &ac, starts_at).await;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn remind_roster(ctx: &Context, ac: &AlertConnector, starts_at: i64) {
+    for role in ROSTER_ROLES
+        .into_iter()
+        .filter(|role| role.is_combat_role())
+    {
+        for entry in ac.get_role(role) {
+            util::notify_user(
+                ctx,
+                entry.user_id,
+                &format!(
+                    "Reminder: **{}** starts <t:{}:R> (<t:{}:F>). You are signed up as {}.",
+                    ac.get_title(),
+                    starts_at,
+                    starts_at,
+                    role.get_name()
+                ),
+            )
+            .await;
+        }
+    }
+    for entry in ac.get_role(RosterRole::Tentative) {
+        util::notify_user(
+            ctx,
+            entry.user_id,
+            &format!(
+                "**{}** starts <t:{}:R> and you are still marked as tentative. \
+                Please confirm by picking a role or marking yourself as not available.",
+                ac.get_title(),
+                starts_at
+            ),
+        )
+        .await;
+    }
+}
+
+/// Minutes before the start to send reminders at, from `REMINDER_OFFSETS` as a comma separated
+/// list. Defaults to a day, an hour and 10 minutes before.
+fn reminder_offsets_from_env() -> Vec<i64> {
+    env::var("REMINDER_OFFSETS")
+        .map(|offsets| {
+            offsets
+                .split(',')
+                .filter_map(|offset| offset.trim().parse::<i64>().ok())
+                .filter(|&offset| offset > 0)
+                .collect()
+        })
+        .unwrap_or_else(|_| vec![24 * 60, 60, 10])
+}
+
+/// Reads a number of minutes from the environment, returned in seconds.
+fn minutes_from_env(key: &str, default: i64) -> i64 {
+    env::var(key)
+        .ok()
+        .and_then(|minutes| minutes.parse::<i64>().ok())
+        .unwrap_or(default)
+        * 60
+}
@@ -0,0 +1,1384 @@
+use crate::alert_connector::RosterChange;
+use crate::alert_status::AlertStatus;
+use crate::error::{Error, Result};
+use crate::group_assignment::GroupAssignment;
+use crate::guild_settings::GuildSettings;
+use crate::requirements::Requirements;
+use crate::roster_entry::{RoleChange, RosterEntry};
+use crate::roster_order::RosterOrder;
+use crate::roster_role::{RosterRole, ROSTER_ROLES};
+use crate::trade_skill::TradeSkill;
+use crate::user_data::UserData;
+use crate::waitlist_entry::WaitlistEntry;
+use crate::war_message::WarMessage;
+use crate::weapon::{Weapon, WEAPONS};
+use crate::{async_trait, AlertConnector, DBManager};
+use chrono::Utc;
+use chrono_tz::Tz;
+use rusqlite::{params, Connection, OptionalExtension};
+use serenity::model::id::RoleId;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS alert_connectors (
+        code TEXT PRIMARY KEY NOT NULL,
+        date TEXT NOT NULL,
+        time TEXT NOT NULL,
+        server TEXT NOT NULL,
+        faction TEXT NOT NULL,
+        territory TEXT NOT NULL,
+        title TEXT NOT NULL,
+        description TEXT NOT NULL DEFAULT '',
+        status TEXT NOT NULL DEFAULT 'open',
+        roster_order TEXT NOT NULL DEFAULT 'signup',
+        type INTEGER NOT NULL,
+        starts_at INTEGER,
+        min_gear_score INTEGER,
+        min_level INTEGER,
+        required_weapon TEXT
+    );
+
+    CREATE TABLE IF NOT EXISTS war_messages (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        code TEXT NOT NULL REFERENCES alert_connectors (code) ON DELETE CASCADE,
+        guild_id INTEGER NOT NULL,
+        channel_id INTEGER NOT NULL,
+        message_id INTEGER NOT NULL,
+        UNIQUE (code, guild_id, channel_id, message_id)
+    );
+
+    CREATE TABLE IF NOT EXISTS group_assignments (
+        code TEXT NOT NULL REFERENCES alert_connectors (code) ON DELETE CASCADE,
+        user_id INTEGER NOT NULL,
+        group_number INTEGER,
+        PRIMARY KEY (code, user_id)
+    );
+
+    CREATE TABLE IF NOT EXISTS army_messages (
+        code TEXT NOT NULL REFERENCES alert_connectors (code) ON DELETE CASCADE,
+        guild_id INTEGER NOT NULL,
+        channel_id INTEGER NOT NULL,
+        message_id INTEGER NOT NULL,
+        PRIMARY KEY (code, guild_id, channel_id)
+    );
+
+    CREATE INDEX IF NOT EXISTS war_messages_message
+        ON war_messages (message_id, channel_id, guild_id);
+
+    CREATE TABLE IF NOT EXISTS roster_entries (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        code TEXT NOT NULL REFERENCES alert_connectors (code) ON DELETE CASCADE,
+        role TEXT NOT NULL,
+        user_id INTEGER NOT NULL,
+        signed_up_at INTEGER NOT NULL DEFAULT 0,
+        joined_at INTEGER NOT NULL DEFAULT 0,
+        added_by INTEGER,
+        UNIQUE (code, role, user_id)
+    );
+
+    CREATE TABLE IF NOT EXISTS roster_history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        code TEXT NOT NULL REFERENCES alert_connectors (code) ON DELETE CASCADE,
+        user_id INTEGER NOT NULL,
+        role TEXT NOT NULL,
+        joined_at INTEGER NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS roster_entries_user ON roster_entries (user_id);
+
+    CREATE TABLE IF NOT EXISTS waitlist_entries (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        code TEXT NOT NULL REFERENCES alert_connectors (code) ON DELETE CASCADE,
+        role TEXT NOT NULL,
+        user_id INTEGER NOT NULL,
+        waiting_since INTEGER NOT NULL DEFAULT 0,
+        added_by INTEGER,
+        UNIQUE (code, user_id)
+    );
+
+    CREATE INDEX IF NOT EXISTS waitlist_entries_user ON waitlist_entries (user_id);
+
+    CREATE TABLE IF NOT EXISTS role_quotas (
+        code TEXT NOT NULL REFERENCES alert_connectors (code) ON DELETE CASCADE,
+        role TEXT NOT NULL,
+        quota INTEGER NOT NULL,
+        PRIMARY KEY (code, role)
+    );
+
+    CREATE TABLE IF NOT EXISTS reminders_sent (
+        code TEXT NOT NULL REFERENCES alert_connectors (code) ON DELETE CASCADE,
+        offset_minutes INTEGER NOT NULL,
+        PRIMARY KEY (code, offset_minutes)
+    );
+
+    CREATE TABLE IF NOT EXISTS register_prompts (
+        user_id INTEGER PRIMARY KEY NOT NULL,
+        sent_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS user_data (
+        user_id INTEGER PRIMARY KEY NOT NULL,
+        username TEXT NOT NULL DEFAULT '',
+        main_hand TEXT,
+        secondary TEXT,
+        level INTEGER NOT NULL DEFAULT 1,
+        gear_score INTEGER NOT NULL DEFAULT 0
+    );
+
+    CREATE TABLE IF NOT EXISTS user_trade_skills (
+        user_id INTEGER NOT NULL REFERENCES user_data (user_id) ON DELETE CASCADE,
+        skill TEXT NOT NULL,
+        level INTEGER NOT NULL,
+        PRIMARY KEY (user_id, skill)
+    );
+
+    CREATE TABLE IF NOT EXISTS user_weapons (
+        user_id INTEGER NOT NULL REFERENCES user_data (user_id) ON DELETE CASCADE,
+        weapon TEXT NOT NULL,
+        level INTEGER NOT NULL,
+        PRIMARY KEY (user_id, weapon)
+    );
+
+    CREATE TABLE IF NOT EXISTS guild_settings (
+        guild_id INTEGER PRIMARY KEY NOT NULL,
+        time_zone TEXT NOT NULL DEFAULT ''
+    );
+
+    CREATE TABLE IF NOT EXISTS permissions (
+        guild_id INTEGER NOT NULL,
+        role_id INTEGER NOT NULL,
+        PRIMARY KEY (guild_id, role_id)
+    );
+";
+
+/// A single schema change. Databases created before migrations were versioned may already have
+/// some of the columns, so only missing ones are added.
+struct Migration {
+    version: u32,
+    description: &'static str,
+    /// Applies the change, or only counts what it would change when `dry_run` is set.
+    /// Returns the number of added columns and changed rows.
+    run: fn(&Connection, bool) -> Result<u64>,
+}
+
+/// All migrations in the order they are applied. Append new ones with the next version and add
+/// their columns to `SCHEMA` as well, which creates new databases.
+const MIGRATIONS: [Migration; 8] = [
+    Migration {
+        version: 1,
+        description: "Add start times to alerts",
+        run: |connection, dry_run| {
+            add_columns(
+                connection,
+                &[("alert_connectors", "starts_at", "INTEGER")],
+                dry_run,
+            )
+        },
+    },
+    Migration {
+        version: 2,
+        description: "Add descriptions to alerts",
+        run: |connection, dry_run| {
+            add_columns(
+                connection,
+                &[(
+                    "alert_connectors",
+                    "description",
+                    "TEXT NOT NULL DEFAULT ''",
+                )],
+                dry_run,
+            )
+        },
+    },
+    Migration {
+        version: 3,
+        description: "Add a status to alerts",
+        run: |connection, dry_run| {
+            let added = add_columns(
+                connection,
+                &[("alert_connectors", "status", "TEXT NOT NULL DEFAULT 'open'")],
+                dry_run,
+            )?;
+            if !dry_run {
+                connection.execute_batch(
+                    "CREATE INDEX IF NOT EXISTS alert_connectors_status
+            ON alert_connectors (status, starts_at);",
+                )?;
+            }
+            Ok(added)
+        },
+    },
+    Migration {
+        version: 4,
+        description: "Add a roster order to alerts",
+        run: |connection, dry_run| {
+            add_columns(
+                connection,
+                &[(
+                    "alert_connectors",
+                    "roster_order",
+                    "TEXT NOT NULL DEFAULT 'signup'",
+                )],
+                dry_run,
+            )
+        },
+    },
+    Migration {
+        version: 5,
+        description: "Add signup times to roster and waitlist entries",
+        run: |connection, dry_run| {
+            add_columns(
+                connection,
+                &[
+                    (
+                        "roster_entries",
+                        "signed_up_at",
+                        "INTEGER NOT NULL DEFAULT 0",
+                    ),
+                    ("roster_entries", "joined_at", "INTEGER NOT NULL DEFAULT 0"),
+                    (
+                        "waitlist_entries",
+                        "waiting_since",
+                        "INTEGER NOT NULL DEFAULT 0",
+                    ),
+                ],
+                dry_run,
+            )
+        },
+    },
+    Migration {
+        version: 6,
+        description: "Record the officer that added roster and waitlist entries",
+        run: |connection, dry_run| {
+            add_columns(
+                connection,
+                &[
+                    ("roster_entries", "added_by", "INTEGER"),
+                    ("waitlist_entries", "added_by", "INTEGER"),
+                ],
+                dry_run,
+            )
+        },
+    },
+    Migration {
+        version: 7,
+        description: "Add signup requirements to alerts",
+        run: |connection, dry_run| {
+            add_columns(
+                connection,
+                &[
+                    ("alert_connectors", "min_gear_score", "INTEGER"),
+                    ("alert_connectors", "min_level", "INTEGER"),
+                    ("alert_connectors", "required_weapon", "TEXT"),
+                ],
+                dry_run,
+            )
+        },
+    },
+    Migration {
+        version: 8,
+        description: "Archive alerts without a start time",
+        run: archive_alerts_without_start,
+    },
+];
+
+/// Applies every migration newer than the `user_version` of the database, recording the new
+/// version after each one. With `dry_run` set nothing is written, only reported.
+fn run_migrations(connection: &mut Connection, dry_run: bool) -> Result<()> {
+    let current: u32 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        if dry_run {
+            println!(
+                "Migration {} ({}) would make {} changes",
+                migration.version,
+                migration.description,
+                (migration.run)(connection, true)?
+            );
+            continue;
+        }
+
+        let transaction = connection.transaction()?;
+        let changed = (migration.run)(&transaction, false)?;
+        transaction.pragma_update(None, "user_version", migration.version)?;
+        transaction.commit()?;
+        println!(
+            "Applied migration {} ({}), made {} changes",
+            migration.version, migration.description, changed
+        );
+    }
+    Ok(())
+}
+
+/// Alerts from before the start time was stored can't be closed or archived on schedule, and
+/// would count as upcoming forever.
+fn archive_alerts_without_start(connection: &Connection, dry_run: bool) -> Result<u64> {
+    let filter = "starts_at IS NULL AND status != 'archived'";
+    if !dry_run {
+        return Ok(connection.execute(
+            &format!(
+                "UPDATE alert_connectors SET status = 'archived' WHERE {}",
+                filter
+            ),
+            [],
+        )? as u64);
+    }
+    // A dry run doesn't add the columns of earlier migrations, without them every alert counts.
+    if !has_column(connection, "alert_connectors", "code")? {
+        return Ok(0);
+    }
+    let query = if has_column(connection, "alert_connectors", "starts_at")?
+        && has_column(connection, "alert_connectors", "status")?
+    {
+        format!("SELECT COUNT(*) FROM alert_connectors WHERE {}", filter)
+    } else {
+        "SELECT COUNT(*) FROM alert_connectors".to_string()
+    };
+    Ok(connection.query_row(&query, [], |row| row.get(0))?)
+}
+
+/// Adds the `(table, column, definition)` columns that are missing. Returns how many were or
+/// would be added.
+fn add_columns(
+    connection: &Connection,
+    columns: &[(&str, &str, &str)],
+    dry_run: bool,
+) -> Result<u64> {
+    let mut added = 0;
+    for &(table, column, definition) in columns {
+        if !has_column(connection, table, column)? {
+            if !dry_run {
+                connection.execute_batch(&format!(
+                    "ALTER TABLE {} ADD COLUMN {} {};",
+                    table, column, definition
+                ))?;
+            }
+            added += 1;
+        }
+    }
+    Ok(added)
+}
+
+fn has_column(connection: &Connection, table: &str, column: &str) -> Result<bool> {
+    Ok(connection
+        .prepare("SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2")?
+        .exists(params![table, column])?)
+}
+
+/// A [`DBManager`] backed by an embedded SQLite database file.
+///
+/// Intended for small self-hosted deployments where running MongoDB is not worth it.
+pub struct SqliteDB {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteDB {
+    /// Opens the database and brings its schema up to date. With `dry_run` set the schema is left
+    /// untouched and the pending migrations are only reported.
+    pub fn open(path: impl AsRef<Path>, dry_run: bool) -> Result<Self> {
+        let mut connection = Connection::open(path)?;
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+        if !dry_run {
+            connection.execute_batch(SCHEMA)?;
+        }
+        run_migrations(&mut connection, dry_run)?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Runs `f` with the connection on the blocking thread pool, so slow queries don't stall the
+    /// async runtime.
+    async fn with_connection<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            f(&mut *connection.lock().map_err(|_| Error::LockPoisoned)?)
+        })
+        .await?
+    }
+
+    async fn update_user_column(
+        &self,
+        user_id: u64,
+        column: &'static str,
+        value: impl rusqlite::ToSql + Send + 'static,
+    ) -> Result<()> {
+        self.with_connection(move |connection| {
+            ensure_user(connection, user_id)?;
+            connection.execute(
+                &format!("UPDATE user_data SET {} = ?2 WHERE user_id = ?1", column),
+                params![user_id as i64, value],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+fn ensure_user(connection: &Connection, user_id: u64) -> Result<()> {
+    connection.execute(
+        "INSERT OR IGNORE INTO user_data (user_id) VALUES (?1)",
+        params![user_id as i64],
+    )?;
+    Ok(())
+}
+
+fn load_codes(
+    connection: &Connection,
+    sql: &str,
+    params: impl rusqlite::Params,
+) -> Result<Vec<String>> {
+    let mut statement = connection.prepare(sql)?;
+    let codes = statement
+        .query_map(params, |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(codes)
+}
+
+fn load_alert_connector(connection: &Connection, code: &str) -> Result<Option<AlertConnector>> {
+    let mut ac = match connection
+        .query_row(
+            "SELECT code, date, time, server, faction, territory, title, type, starts_at,
+                description, status, roster_order, min_gear_score, min_level, required_weapon
+             FROM alert_connectors WHERE code = ?1",
+            params![code],
+            |row| {
+                Ok(AlertConnector {
+                    code: row.get(0)?,
+                    date: row.get(1)?,
+                    time: row.get(2)?,
+                    server: row.get(3)?,
+                    faction: row.get(4)?,
+                    territory: row.get(5)?,
+                    title: row.get(6)?,
+                    r#type: row.get(7)?,
+                    starts_at: row.get(8)?,
+                    description: row.get(9)?,
+                    status: AlertStatus::try_from(row.get::<_, String>(10)?).unwrap_or_default(),
+                    roster_order: RosterOrder::try_from(row.get::<_, String>(11)?)
+                        .unwrap_or_default(),
+                    requirements: Requirements {
+                        min_gear_score: row.get(12)?,
+                        min_level: row.get(13)?,
+                        required_weapon: row
+                            .get::<_, Option<String>>(14)?
+                            .and_then(|weapon| Weapon::try_from(weapon).ok()),
+                    },
+                    ..Default::default()
+                })
+            },
+        )
+        .optional()?
+    {
+        Some(ac) => ac,
+        None => return Ok(None),
+    };
+
+    let mut statement = connection.prepare(
+        "SELECT guild_id, channel_id, message_id FROM war_messages
+         WHERE code = ?1 ORDER BY id",
+    )?;
+    ac.war_messages = statement
+        .query_map(params![code], |row| {
+            Ok(WarMessage::new(
+                row.get::<_, i64>(0)? as u64,
+                row.get::<_, i64>(1)? as u64,
+                row.get::<_, i64>(2)? as u64,
+            ))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let mut statement = connection.prepare(
+        "SELECT user_id, group_number FROM group_assignments WHERE code = ?1 ORDER BY rowid",
+    )?;
+    ac.assignments = statement
+        .query_map(params![code], |row| {
+            Ok(GroupAssignment::new(
+                row.get::<_, i64>(0)? as u64,
+                row.get(1)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let mut statement = connection
+        .prepare("SELECT guild_id, channel_id, message_id FROM army_messages WHERE code = ?1")?;
+    ac.army_messages = statement
+        .query_map(params![code], |row| {
+            Ok(WarMessage::new(
+                row.get::<_, i64>(0)? as u64,
+                row.get::<_, i64>(1)? as u64,
+                row.get::<_, i64>(2)? as u64,
+            ))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let mut statement = connection.prepare(
+        "SELECT role, user_id, signed_up_at, joined_at, added_by FROM roster_entries
+         WHERE code = ?1 ORDER BY id",
+    )?;
+    let entries = statement.query_map(params![code], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            RosterEntry {
+                user_id: row.get::<_, i64>(1)? as u64,
+                signed_up_at: row.get(2)?,
+                joined_at: row.get(3)?,
+                added_by: row.get::<_, Option<i64>>(4)?.map(|id| id as u64),
+                history: vec![],
+            },
+        ))
+    })?;
+    for entry in entries {
+        let (role, entry) = entry?;
+        if let Some(role) = RosterRole::from_field(&role) {
+            ac.get_role_mut(role).push(entry);
+        }
+    }
+
+    let mut statement = connection.prepare(
+        "SELECT user_id, role, joined_at FROM roster_history WHERE code = ?1 ORDER BY id",
+    )?;
+    let changes = statement.query_map(params![code], |row| {
+        Ok((
+            row.get::<_, i64>(0)? as u64,
+            row.get::<_, String>(1)?,
+            row.get::<_, i64>(2)?,
+        ))
+    })?;
+    for change in changes {
+        let (user_id, role, joined_at) = change?;
+        if let (Some(role), Some(current)) =
+            (RosterRole::from_field(&role), ac.get_user_role(user_id))
+        {
+            if let Some(entry) = ac
+                .get_role_mut(current)
+                .iter_mut()
+                .find(|entry| entry.user_id == user_id)
+            {
+                entry.history.push(RoleChange { role, joined_at });
+            }
+        }
+    }
+
+    let mut statement = connection.prepare(
+        "SELECT role, user_id, waiting_since, added_by FROM waitlist_entries
+         WHERE code = ?1 ORDER BY id",
+    )?;
+    let entries = statement.query_map(params![code], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, i64>(1)? as u64,
+            row.get::<_, i64>(2)?,
+            row.get::<_, Option<i64>>(3)?.map(|id| id as u64),
+        ))
+    })?;
+    for entry in entries {
+        let (role, user_id, waiting_since, added_by) = entry?;
+        if let Some(role) = RosterRole::from_field(&role) {
+            ac.waitlist
+                .push(WaitlistEntry::new(user_id, role, waiting_since, added_by));
+        }
+    }
+
+    let mut statement =
+        connection.prepare("SELECT role, quota FROM role_quotas WHERE code = ?1")?;
+    let quotas = statement.query_map(params![code], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?))
+    })?;
+    for quota in quotas {
+        let (role, quota) = quota?;
+        if let Some(role) = RosterRole::from_field(&role) {
+            ac.quotas.insert(role, quota);
+        }
+    }
+
+    Ok(Some(ac))
+}
+
+/// Applies `f` to the roster of the alert and writes the roster and waitlist back, keeping their
+/// order. Runs in a single transaction.
+fn update_roster(
+    connection: &mut Connection,
+    uuid: Uuid,
+    f: impl FnOnce(&mut AlertConnector) -> RosterChange,
+) -> Result<RosterChange> {
+    let transaction = connection.transaction()?;
+    let mut ac = match load_alert_connector(&transaction, &uuid.to_string())? {
+        Some(ac) => ac,
+        None => return Ok(RosterChange::default()),
+    };
+    let change = f(&mut ac);
+    save_roster(&transaction, &ac)?;
+    transaction.commit()?;
+    Ok(change)
+}
+
+/// Replaces the stored roster entries, their history and the waitlist with those of `ac`.
+fn save_roster(connection: &Connection, ac: &AlertConnector) -> Result<()> {
+    connection.execute(
+        "DELETE FROM roster_entries WHERE code = ?1",
+        params![&ac.code],
+    )?;
+    connection.execute(
+        "DELETE FROM roster_history WHERE code = ?1",
+        params![&ac.code],
+    )?;
+    for role in ROSTER_ROLES {
+        for entry in ac.get_role(role) {
+            connection.execute(
+                "INSERT OR IGNORE INTO roster_entries
+                    (code, role, user_id, signed_up_at, joined_at, added_by)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    &ac.code,
+                    role.get_field(),
+                    entry.user_id as i64,
+                    entry.signed_up_at,
+                    entry.joined_at,
+                    entry.added_by.map(|id| id as i64)
+                ],
+            )?;
+            for change in &entry.history {
+                connection.execute(
+                    "INSERT INTO roster_history (code, user_id, role, joined_at)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        &ac.code,
+                        entry.user_id as i64,
+                        change.role.get_field(),
+                        change.joined_at
+                    ],
+                )?;
+            }
+        }
+    }
+    connection.execute(
+        "DELETE FROM waitlist_entries WHERE code = ?1",
+        params![&ac.code],
+    )?;
+    for entry in &ac.waitlist {
+        connection.execute(
+            "INSERT OR IGNORE INTO waitlist_entries
+                (code, role, user_id, waiting_since, added_by)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                &ac.code,
+                entry.role.get_field(),
+                entry.user_id as i64,
+                entry.waiting_since,
+                entry.added_by.map(|id| id as i64)
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl DBManager for SqliteDB {
+    async fn create_indexes(&self) -> Result<()> {
+        // The indexes are part of the schema created in `open`.
+        Ok(())
+    }
+
+    async fn get_alert_connector(&self, uuid: Uuid) -> Result<Option<AlertConnector>> {
+        self.with_connection(move |connection| load_alert_connector(connection, &uuid.to_string()))
+            .await
+    }
+
+    async fn get_alert_connectors(&self) -> Result<Vec<AlertConnector>> {
+        self.with_connection(|connection| {
+            let mut connectors = vec![];
+            for code in load_codes(connection, "SELECT code FROM alert_connectors", [])? {
+                if let Some(ac) = load_alert_connector(connection, &code)? {
+                    connectors.push(ac);
+                }
+            }
+            Ok(connectors)
+        })
+        .await
+    }
+
+    async fn get_alert_connector_by_message(
+        &self,
+        message: WarMessage,
+    ) -> Result<Option<AlertConnector>> {
+        self.with_connection(move |connection| {
+            match connection
+                .query_row(
+                    "SELECT code FROM war_messages
+                     WHERE message_id = ?1 AND channel_id = ?2 AND guild_id = ?3",
+                    params![
+                        message.get_message_id() as i64,
+                        message.get_channel_id() as i64,
+                        message.get_guild_id() as i64
+                    ],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()?
+            {
+                Some(code) => load_alert_connector(connection, &code),
+                None => Ok(None),
+            }
+        })
+        .await
+    }
+
+    async fn get_alert_connectors_with_user_id(&self, user_id: u64) -> Result<Vec<AlertConnector>> {
+        self.with_connection(move |connection| {
+            let mut connectors = vec![];
+            for code in load_codes(
+                connection,
+                "SELECT code FROM roster_entries WHERE user_id = ?1
+                 UNION SELECT code FROM waitlist_entries WHERE user_id = ?1",
+                params![user_id as i64],
+            )? {
+                if let Some(ac) = load_alert_connector(connection, &code)? {
+                    if ac.status != AlertStatus::Cancelled && ac.get_users().contains(&user_id) {
+                        connectors.push(ac);
+                    }
+                }
+            }
+            Ok(connectors)
+        })
+        .await
+    }
+
+    async fn get_upcoming_alert_connectors_with_user_id(
+        &self,
+        user_id: u64,
+        since: i64,
+    ) -> Result<Vec<AlertConnector>> {
+        self.with_connection(move |connection| {
+            let mut connectors = vec![];
+            for code in load_codes(
+                connection,
+                "SELECT code FROM alert_connectors
+                 WHERE code IN (
+                        SELECT code FROM roster_entries WHERE user_id = ?1
+                        UNION SELECT code FROM waitlist_entries WHERE user_id = ?1
+                    )
+                    AND starts_at >= ?2
+                    AND status NOT IN ('cancelled', 'archived')",
+                params![user_id as i64, since],
+            )? {
+                if let Some(ac) = load_alert_connector(connection, &code)? {
+                    connectors.push(ac);
+                }
+            }
+            Ok(connectors)
+        })
+        .await
+    }
+
+    async fn get_user_data(&self, user_id: u64) -> Result<Option<UserData>> {
+        self.with_connection(move |connection| {
+            let mut user_data = match connection
+                .query_row(
+                    "SELECT username, main_hand, secondary, level, gear_score
+                     FROM user_data WHERE user_id = ?1",
+                    params![user_id as i64],
+                    |row| {
+                        Ok(UserData {
+                            username: row.get(0)?,
+                            main_hand: row
+                                .get::<_, Option<String>>(1)?
+                                .and_then(|w| Weapon::try_from(w).ok()),
+                            secondary: row
+                                .get::<_, Option<String>>(2)?
+                                .and_then(|w| Weapon::try_from(w).ok()),
+                            level: row.get(3)?,
+                            gear_score: row.get(4)?,
+                            ..Default::default()
+                        })
+                    },
+                )
+                .optional()?
+            {
+                Some(user_data) => user_data,
+                None => return Ok(None),
+            };
+
+            let mut statement = connection
+                .prepare("SELECT skill, level FROM user_trade_skills WHERE user_id = ?1")?;
+            for skill in statement.query_map(params![user_id as i64], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, u8>(1)?))
+            })? {
+                let (skill, level) = skill?;
+                if let Ok(skill) = TradeSkill::try_from(skill) {
+                    user_data.trade_skills.insert(skill, level);
+                }
+            }
+
+            let mut statement =
+                connection.prepare("SELECT weapon, level FROM user_weapons WHERE user_id = ?1")?;
+            for weapon in statement.query_map(params![user_id as i64], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, u8>(1)?))
+            })? {
+                let (weapon, level) = weapon?;
+                if let Ok(weapon) = Weapon::try_from(weapon) {
+                    user_data.weapons.insert(weapon, level);
+                }
+            }
+
+            Ok(Some(user_data))
+        })
+        .await
+    }
+
+    async fn has_permission(&self, guild_id: u64, roles: &[RoleId]) -> Result<bool> {
+        let roles = roles.to_vec();
+        self.with_connection(move |connection| {
+            let mut statement = connection
+                .prepare("SELECT 1 FROM permissions WHERE guild_id = ?1 AND role_id = ?2")?;
+            for role in roles {
+                if statement.exists(params![guild_id as i64, role.0 as i64])? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        })
+        .await
+    }
+
+    async fn add_permission(&self, guild_id: u64, role_id: u64) -> Result<()> {
+        self.with_connection(move |connection| {
+            connection.execute(
+                "INSERT OR IGNORE INTO permissions (guild_id, role_id) VALUES (?1, ?2)",
+                params![guild_id as i64, role_id as i64],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn remove_permission(&self, guild_id: u64, role_id: u64) -> Result<()> {
+        self.with_connection(move |connection| {
+            connection.execute(
+                "DELETE FROM permissions WHERE guild_id = ?1 AND role_id = ?2",
+                params![guild_id as i64, role_id as i64],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_guild_settings(&self, guild_id: u64) -> Result<GuildSettings> {
+        self.with_connection(move |connection| {
+            Ok(connection
+                .query_row(
+                    "SELECT time_zone FROM guild_settings WHERE guild_id = ?1",
+                    params![guild_id as i64],
+                    |row| {
+                        Ok(GuildSettings {
+                            time_zone: row.get(0)?,
+                        })
+                    },
+                )
+                .optional()?
+                .unwrap_or_default())
+        })
+        .await
+    }
+
+    async fn set_guild_time_zone(&self, guild_id: u64, time_zone: Tz) -> Result<()> {
+        self.with_connection(move |connection| {
+            connection.execute(
+                "INSERT INTO guild_settings (guild_id, time_zone) VALUES (?1, ?2)
+                 ON CONFLICT (guild_id) DO UPDATE SET time_zone = excluded.time_zone",
+                params![guild_id as i64, time_zone.name()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn channel_contains_war_message(
+        &self,
+        guild_id: u64,
+        channel_id: u64,
+        uuid: Uuid,
+    ) -> Result<bool> {
+        self.with_connection(move |connection| {
+            Ok(connection
+                .prepare(
+                    "SELECT 1 FROM war_messages
+                     WHERE code = ?1 AND guild_id = ?2 AND channel_id = ?3",
+                )?
+                .exists(params![
+                    uuid.to_string(),
+                    guild_id as i64,
+                    channel_id as i64
+                ])?)
+        })
+        .await
+    }
+
+    async fn add_war_message(&self, uuid: Uuid, message: WarMessage) -> Result<()> {
+        self.with_connection(move |connection| {
+            connection.execute(
+                "INSERT OR IGNORE INTO war_messages (code, guild_id, channel_id, message_id)
+                 SELECT code, ?2, ?3, ?4 FROM alert_connectors WHERE code = ?1",
+                params![
+                    uuid.to_string(),
+                    message.get_guild_id() as i64,
+                    message.get_channel_id() as i64,
+                    message.get_message_id() as i64
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn create_alert_connector(&self, ac: &AlertConnector) -> Result<()> {
+        let ac = ac.clone();
+        self.with_connection(move |connection| {
+            let transaction = connection.transaction()?;
+            transaction.execute(
+                "INSERT INTO alert_connectors
+                 (code, date, time, server, faction, territory, title, type, starts_at, description,
+                    status, roster_order, min_gear_score, min_level, required_weapon)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+                params![
+                    ac.code,
+                    ac.date,
+                    ac.time,
+                    ac.server,
+                    ac.faction,
+                    ac.territory,
+                    ac.title,
+                    ac.r#type,
+                    ac.starts_at,
+                    ac.description,
+                    String::from(ac.status),
+                    String::from(ac.roster_order),
+                    ac.requirements.min_gear_score,
+                    ac.requirements.min_level,
+                    ac.requirements.required_weapon.map(String::from)
+                ],
+            )?;
+            for message in &ac.war_messages {
+                transaction.execute(
+                    "INSERT OR IGNORE INTO war_messages (code, guild_id, channel_id, message_id)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        ac.code,
+                        message.get_guild_id() as i64,
+                        message.get_channel_id() as i64,
+                        message.get_message_id() as i64
+                    ],
+                )?;
+            }
+            for (role, quota) in &ac.quotas {
+                transaction.execute(
+                    "INSERT INTO role_quotas (code, role, quota) VALUES (?1, ?2, ?3)",
+                    params![ac.code, role.get_field(), quota],
+                )?;
+            }
+            save_roster(&transaction, &ac)?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_alert_details(&self, ac: &AlertConnector) -> Result<()> {
+        let ac = ac.clone();
+        self.with_connection(move |connection| {
+            let transaction = connection.transaction()?;
+            transaction.execute(
+                "DELETE FROM reminders_sent
+                 WHERE code = ?1
+                    AND (SELECT starts_at FROM alert_connectors WHERE code = ?1) IS NOT ?2",
+                params![ac.code, ac.starts_at],
+            )?;
+            transaction.execute(
+                "UPDATE alert_connectors
+                 SET date = ?2, time = ?3, territory = ?4, title = ?5, description = ?6,
+                    starts_at = ?7
+                 WHERE code = ?1",
+                params![
+                    ac.code,
+                    ac.date,
+                    ac.time,
+                    ac.territory,
+                    ac.title,
+                    ac.description,
+                    ac.starts_at
+                ],
+            )?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn set_alert_status(&self, uuid: Uuid, status: AlertStatus) -> Result<()> {
+        self.with_connection(move |connection| {
+            connection.execute(
+                "UPDATE alert_connectors SET status = ?2 WHERE code = ?1",
+                params![uuid.to_string(), String::from(status)],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn set_roster_order(&self, uuid: Uuid, order: RosterOrder) -> Result<()> {
+        self.with_connection(move |connection| {
+            connection.execute(
+                "UPDATE alert_connectors SET roster_order = ?2 WHERE code = ?1",
+                params![uuid.to_string(), String::from(order)],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn set_group_assignment(&self, uuid: Uuid, assignment: GroupAssignment) -> Result<()> {
+        self.with_connection(move |connection| {
+            let transaction = connection.transaction()?;
+            transaction.execute(
+                "DELETE FROM group_assignments WHERE code = ?1 AND user_id = ?2",
+                params![uuid.to_string(), assignment.user_id as i64],
+            )?;
+            transaction.execute(
+                "INSERT INTO group_assignments (code, user_id, group_number)
+                 SELECT code, ?2, ?3 FROM alert_connectors WHERE code = ?1",
+                params![
+                    uuid.to_string(),
+                    assignment.user_id as i64,
+                    assignment.group
+                ],
+            )?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn set_army_message(&self, uuid: Uuid, message: WarMessage) -> Result<()> {
+        self.with_connection(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO army_messages (code, guild_id, channel_id, message_id)
+                 SELECT code, ?2, ?3, ?4 FROM alert_connectors WHERE code = ?1",
+                params![
+                    uuid.to_string(),
+                    message.get_guild_id() as i64,
+                    message.get_channel_id() as i64,
+                    message.get_message_id() as i64
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_due_alert_connectors(
+        &self,
+        status: AlertStatus,
+        before: i64,
+    ) -> Result<Vec<AlertConnector>> {
+        self.with_connection(move |connection| {
+            let mut connectors = vec![];
+            for code in load_codes(
+                connection,
+                "SELECT code FROM alert_connectors WHERE status = ?1 AND starts_at < ?2",
+                params![String::from(status), before],
+            )? {
+                if let Some(ac) = load_alert_connector(connection, &code)? {
+                    connectors.push(ac);
+                }
+            }
+            Ok(connectors)
+        })
+        .await
+    }
+
+    async fn mark_reminder_sent(&self, uuid: Uuid, offset: i64) -> Result<bool> {
+        self.with_connection(move |connection| {
+            Ok(connection.execute(
+                "INSERT OR IGNORE INTO reminders_sent (code, offset_minutes) VALUES (?1, ?2)",
+                params![uuid.to_string(), offset],
+            )? == 1)
+        })
+        .await
+    }
+
+    async fn mark_register_prompt_sent(&self, user_id: u64) -> Result<bool> {
+        self.with_connection(move |connection| {
+            Ok(connection.execute(
+                "INSERT OR IGNORE INTO register_prompts (user_id, sent_at) VALUES (?1, ?2)",
+                params![user_id as i64, Utc::now().timestamp()],
+            )? == 1)
+        })
+        .await
+    }
+
+    async fn update_main_hand(&self, user_id: u64, weapon: Weapon) -> Result<()> {
+        self.update_user_column(user_id, "main_hand", String::from(weapon))
+            .await
+    }
+
+    async fn update_secondary(&self, user_id: u64, weapon: Weapon) -> Result<()> {
+        self.update_user_column(user_id, "secondary", String::from(weapon))
+            .await
+    }
+
+    async fn update_level(&self, user_id: u64, level: u8) -> Result<()> {
+        self.update_user_column(user_id, "level", level).await
+    }
+
+    async fn update_gear_score(&self, user_id: u64, gear_score: u16) -> Result<()> {
+        self.update_user_column(user_id, "gear_score", gear_score)
+            .await
+    }
+
+    async fn update_trade_skill(&self, user_id: u64, level: u8, skill: TradeSkill) -> Result<()> {
+        self.with_connection(move |connection| {
+            ensure_user(connection, user_id)?;
+            connection.execute(
+                "INSERT INTO user_trade_skills (user_id, skill, level) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (user_id, skill) DO UPDATE SET level = excluded.level",
+                params![user_id as i64, String::from(skill), level],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_weapon_level(&self, user_id: u64, level: u8, weapon: Weapon) -> Result<()> {
+        self.with_connection(move |connection| {
+            ensure_user(connection, user_id)?;
+            connection.execute(
+                "INSERT INTO user_weapons (user_id, weapon, level) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (user_id, weapon) DO UPDATE SET level = excluded.level",
+                params![user_id as i64, String::from(weapon), level],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_username(&self, user_id: u64, username: &str) -> Result<()> {
+        self.update_user_column(user_id, "username", username.to_string())
+            .await
+    }
+
+    async fn signup(
+        &self,
+        uuid: Uuid,
+        user_id: u64,
+        role: RosterRole,
+        added_by: Option<u64>,
+    ) -> Result<RosterChange> {
+        self.with_connection(move |connection| {
+            update_roster(connection, uuid, |ac| ac.signup(user_id, role, added_by))
+        })
+        .await
+    }
+
+    async fn withdraw(&self, uuid: Uuid, user_id: u64, role: RosterRole) -> Result<RosterChange> {
+        self.with_connection(move |connection| {
+            update_roster(connection, uuid, |ac| ac.withdraw(user_id, role))
+        })
+        .await
+    }
+
+    async fn remove_duplicate_entries(&self, uuid: Uuid) -> Result<()> {
+        self.with_connection(move |connection| {
+            update_roster(connection, uuid, |ac| {
+                ac.remove_duplicate_entries();
+                RosterChange::default()
+            })?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_user_data_stats(
+        &self,
+    ) -> Result<(u32, u32, HashMap<Weapon, u32>, HashMap<Weapon, u32>)> {
+        self.with_connection(|connection| {
+            let average_level = connection.query_row(
+                "SELECT COALESCE(SUM(level) / COUNT(*), 0) FROM user_data WHERE level > 1",
+                [],
+                |row| row.get::<_, u32>(0),
+            )?;
+            let average_gear_score = connection.query_row(
+                "SELECT COALESCE(SUM(gear_score) / COUNT(*), 0) FROM user_data
+                 WHERE gear_score > 0",
+                [],
+                |row| row.get::<_, u32>(0),
+            )?;
+
+            let weapon_counts = |column: &str| -> Result<HashMap<Weapon, u32>> {
+                let mut counts: HashMap<Weapon, u32> =
+                    WEAPONS.iter().map(|&weapon| (weapon, 0)).collect();
+                let mut statement = connection.prepare(&format!(
+                    "SELECT {0}, COUNT(*) FROM user_data WHERE {0} IS NOT NULL GROUP BY {0}",
+                    column
+                ))?;
+                for count in statement.query_map([], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?))
+                })? {
+                    let (weapon, count) = count?;
+                    if let Ok(weapon) = Weapon::try_from(weapon) {
+                        counts.insert(weapon, count);
+                    }
+                }
+                Ok(counts)
+            };
+
+            Ok((
+                average_level,
+                average_gear_score,
+                weapon_counts("main_hand")?,
+                weapon_counts("secondary")?,
+            ))
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrations_upgrade_old_databases_once() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE alert_connectors (code TEXT PRIMARY KEY NOT NULL, starts_at INTEGER);
+                CREATE TABLE roster_entries (code TEXT NOT NULL, user_id INTEGER NOT NULL);
+                CREATE TABLE waitlist_entries (code TEXT NOT NULL, user_id INTEGER NOT NULL);
+                INSERT INTO alert_connectors (code, starts_at) VALUES ('legacy', NULL), ('new', 1000);",
+            )
+            .unwrap();
+        run_migrations(&mut connection, true).unwrap();
+        assert!(!has_column(&connection, "alert_connectors", "status").unwrap());
+
+        run_migrations(&mut connection, false).unwrap();
+        assert!(has_column(&connection, "alert_connectors", "status").unwrap());
+        assert!(has_column(&connection, "alert_connectors", "required_weapon").unwrap());
+        let version: u32 = connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len() as u32);
+        let archived: Vec<String> = connection
+            .prepare("SELECT code FROM alert_connectors WHERE status = 'archived'")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(archived, vec!["legacy".to_string()]);
+        run_migrations(&mut connection, false).unwrap();
+    }
+
+    #[tokio::test]
+    async fn round_trips_alerts_and_user_data() {
+        let db = SqliteDB::open(":memory:", false).unwrap();
+        let uuid = Uuid::new_v5(&Uuid::NAMESPACE_OID, b"sqlite round trip");
+        db.create_alert_connector(&AlertConnector {
+            code: uuid.to_string(),
+            date: "Sat 1. Oct".to_string(),
+            time: "08:00pm".to_string(),
+            territory: "everfall".to_string(),
+            starts_at: Some(1_000),
+            quotas: HashMap::from([(RosterRole::Healer, 1)]),
+            requirements: Requirements {
+                min_gear_score: Some(500),
+                ..Default::default()
+            },
+            war_messages: vec![WarMessage::new(1, 2, 3)],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        db.signup(uuid, 10, RosterRole::Healer, Some(20))
+            .await
+            .unwrap();
+
+        let ac = db
+            .get_alert_connector_by_message(WarMessage::new(1, 2, 3))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(ac.code, uuid.to_string());
+        assert_eq!(ac.get_quota(RosterRole::Healer), Some(1));
+        assert_eq!(ac.requirements.min_gear_score, Some(500));
+        assert_eq!(ac.healers.len(), 1);
+        assert_eq!(ac.healers[0].user_id, 10);
+        assert_eq!(ac.healers[0].added_by, Some(20));
+
+        db.update_username(10, "Mender").await.unwrap();
+        db.update_weapon_level(10, 20, Weapon::LifeStaff)
+            .await
+            .unwrap();
+        let user_data = db.get_user_data(10).await.unwrap().unwrap();
+        assert_eq!(user_data.username, "Mender");
+        assert_eq!(user_data.get_weapon_level(Weapon::LifeStaff), 20);
+
+        db.withdraw(uuid, 10, RosterRole::Healer).await.unwrap();
+        assert!(db
+            .get_alert_connectors_with_user_id(10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn upcoming_alerts_leave_out_past_ones_and_those_without_a_start() {
+        let db = SqliteDB::open(":memory:", false).unwrap();
+        let upcoming = Uuid::new_v5(&Uuid::NAMESPACE_OID, b"upcoming");
+        let past = Uuid::new_v5(&Uuid::NAMESPACE_OID, b"past");
+        let legacy = Uuid::new_v5(&Uuid::NAMESPACE_OID, b"legacy");
+        for (uuid, starts_at) in [(upcoming, Some(3_000)), (past, Some(1_000)), (legacy, None)] {
+            db.create_alert_connector(&AlertConnector {
+                code: uuid.to_string(),
+                starts_at,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+            db.signup(uuid, 10, RosterRole::Mdps, None).await.unwrap();
+        }
+
+        let found = db
+            .get_upcoming_alert_connectors_with_user_id(10, 2_000)
+            .await
+            .unwrap();
+        assert_eq!(
+            found.into_iter().map(|ac| ac.code).collect::<Vec<_>>(),
+            vec![upcoming.to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn rescheduling_clears_sent_reminders() {
+        let db = SqliteDB::open(":memory:", false).unwrap();
+        let uuid = Uuid::new_v5(&Uuid::NAMESPACE_OID, b"rescheduled");
+        let mut ac = AlertConnector {
+            code: uuid.to_string(),
+            starts_at: Some(1_000),
+            ..Default::default()
+        };
+        db.create_alert_connector(&ac).await.unwrap();
+        assert!(db.mark_reminder_sent(uuid, 60).await.unwrap());
+
+        ac.title = "Renamed".to_string();
+        db.update_alert_details(&ac).await.unwrap();
+        assert!(!db.mark_reminder_sent(uuid, 60).await.unwrap());
+
+        ac.starts_at = Some(100_000);
+        db.update_alert_details(&ac).await.unwrap();
+        assert!(db.mark_reminder_sent(uuid, 60).await.unwrap());
+    }
+}
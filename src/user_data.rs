@@ -3,7 +3,7 @@ use crate::weapon::{Weapon, WEAPONS};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct UserData {
     #[serde(default)]
     pub username: String,
@@ -1,10 +1,25 @@
-use crate::{AlertConnector, DBManager};
+use crate::alert_connector::Promotion;
+use crate::alert_status::AlertStatus;
+use crate::army::{Army, GROUP_COUNT, GROUP_SIZE};
+use crate::component_handler::{add_signup_components, SignupMode};
+use crate::error::Result;
+use crate::roster_entry::RosterEntry;
+use crate::roster_order::RosterOrder;
+use crate::roster_role::{RosterRole, ROSTER_ROLES};
+use crate::user_data::UserData;
+use crate::{AlertConnector, DBManager, SignupModeKey};
 use serenity::builder::CreateEmbed;
-use serenity::model::prelude::{ChannelId, ReactionType};
+use serenity::json::Value;
+use serenity::model::prelude::{ChannelId, ReactionType, UserId};
 use serenity::prelude::Context;
+use std::collections::HashMap;
+use std::str::FromStr;
 use uuid::Uuid;
 
-pub const REACTIONS: [char; 8] = ['🛡', '🗡', '🏹', '🪄', '❤', '💥', '❓', '⛔'];
+/// Discord rejects embeds with a field value longer than this.
+const FIELD_VALUE_LIMIT: usize = 1024;
+/// Discord rejects embeds with more text than this in total.
+const EMBED_LIMIT: usize = 6000;
 
 pub fn convert_to_emoji(s: &str) -> String {
     let mut result = String::new();
@@ -23,210 +38,768 @@ pub fn convert_to_emoji(s: &str) -> String {
     result
 }
 
-pub async fn update_embeds(uuid: Uuid, ctx: &Context, db_client: &mongodb::Client) {
-    if let Some(ac) = db_client.get_alert_connector(uuid).await {
+pub async fn update_embeds(
+    uuid: Uuid,
+    ctx: &Context,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<()> {
+    if let Some(ac) = db_client.get_alert_connector(uuid).await? {
+        let embed = build_embed(&ac, db_client).await?;
         for war_message in &ac.war_messages {
-            if let Ok(guild) = ctx.http.get_guild(war_message.get_guild_id()).await {
-                if let Ok(channels) = guild.channels(ctx).await {
-                    if let Some(channel) = channels.get(&ChannelId(war_message.get_channel_id())) {
-                        if let Ok(mut message) =
-                            channel.message(ctx, war_message.get_message_id()).await
-                        {
-                            if let Some(embed) = message.embeds.get(0) {
-                                let mut new_embed = CreateEmbed::default();
-
-                                new_embed
-                                    .title(embed.title.as_ref().unwrap())
-                                    .description(embed.description.as_ref().unwrap())
-                                    .fields(
-                                        embed.fields[0..3]
-                                            .iter()
-                                            .map(|e| (&e.name, &e.value, e.inline)),
-                                    );
-
-                                fill_embed(&mut new_embed, &ac, db_client).await;
-
-                                let note = embed.fields.last().unwrap();
-                                new_embed.field(&note.name, &note.value, note.inline);
-
-                                new_embed.footer(|f| {
-                                    let footer = embed.footer.as_ref().unwrap();
-                                    f.text(&footer.text)
-                                });
-
-                                if let Err(why) =
-                                    message.edit(ctx, |m| m.set_embed(new_embed)).await
-                                {
-                                    println!("Failed to update embed: {}", why);
-                                }
-
-                                for reaction in REACTIONS {
-                                    if !message.reactions.iter().any(|r| {
-                                        if let ReactionType::Unicode(emoji) = &r.reaction_type {
-                                            *emoji == reaction.to_string()
-                                        } else {
-                                            false
-                                        }
-                                    }) {
-                                        message
-                                            .react(ctx, reaction)
-                                            .await
-                                            .expect("Failed to add reaction");
-                                    }
-                                }
-                            }
+            if let Ok(mut message) = ChannelId(war_message.get_channel_id())
+                .message(ctx, war_message.get_message_id())
+                .await
+            {
+                if !ac.is_open() {
+                    // Closed alerts no longer take signups, so their buttons and reactions go.
+                    if let Err(why) = message
+                        .edit(ctx, |m| m.set_embed(embed.clone()).components(|c| c))
+                        .await
+                    {
+                        println!("Failed to update embed: {}", why);
+                    }
+                    if !message.reactions.is_empty() {
+                        if let Err(why) = message.delete_reactions(ctx).await {
+                            println!("Failed to remove reactions: {}", why);
+                        }
+                    }
+                    continue;
+                }
+
+                // A message without buttons or reactions had them removed when it was closed.
+                // Reopened alerts get the controls of the configured signup mode back.
+                let restore_buttons = message.components.is_empty()
+                    && message.reactions.is_empty()
+                    && ctx
+                        .data
+                        .read()
+                        .await
+                        .get::<SignupModeKey>()
+                        .copied()
+                        .unwrap_or_default()
+                        == SignupMode::Buttons;
+
+                if let Err(why) = message
+                    .edit(ctx, |m| {
+                        m.set_embed(embed.clone());
+                        if restore_buttons {
+                            m.components(add_signup_components);
+                        }
+                        m
+                    })
+                    .await
+                {
+                    println!("Failed to update embed: {}", why);
+                }
+
+                // Alerts with signup buttons don't use reactions.
+                if restore_buttons || !message.components.is_empty() {
+                    continue;
+                }
+
+                for role in ROSTER_ROLES {
+                    if !message.reactions.iter().any(|r| {
+                        if let ReactionType::Unicode(emoji) = &r.reaction_type {
+                            *emoji == role.get_emoji().to_string()
+                        } else {
+                            false
                         }
+                    }) {
+                        message.react(ctx, role.get_emoji()).await?;
                     }
                 }
             }
         }
     }
+    Ok(())
 }
 
-pub async fn fill_embed(embed: &mut CreateEmbed, ac: &AlertConnector, db_client: &mongodb::Client) {
-    let mut tanks = String::new();
-    let mut erdps = String::new();
-    let mut prdps = String::new();
-    let mut mdps = String::new();
-    let mut healers = String::new();
-    let mut tentative = String::new();
-    let mut not_available = String::new();
-    let mut artillery = String::new();
+/// Builds the complete alert embed from the stored connector.
+pub async fn build_embed(
+    ac: &AlertConnector,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<CreateEmbed> {
+    let mut embed = CreateEmbed::default()
+        .title(if ac.is_open() {
+            ac.get_title().to_string()
+        } else {
+            format!("{} - {}", ac.status.get_label(), ac.get_title())
+        })
+        .description(if ac.description.is_empty() {
+            convert_to_emoji(&ac.territory)
+        } else {
+            ac.description.clone()
+        })
+        .to_owned();
+
+    if let Some(starts_at) = ac.starts_at {
+        embed
+            .field(
+                ":calendar_spiral: Date",
+                format!("<t:{}:F>", starts_at),
+                true,
+            )
+            .field("\u{200B}", "\u{200B}", true)
+            .field(":clock1: Starts", format!("<t:{}:R>", starts_at), true);
+    } else {
+        embed
+            .field(format!(":calendar_spiral: {}", ac.date), "\u{200B}", true)
+            .field("\u{200B}", "\u{200B}", true)
+            .field(format!(":clock1: {}", ac.time), "\u{200B}", true);
+    }
+
+    if !ac.requirements.is_empty() {
+        embed.field(":warning: Requirements", ac.requirements.get_label(), false);
+    }
 
-    fill_string_from_list(&mut tanks, &ac.tanks, db_client).await;
-    fill_string_from_list(&mut erdps, &ac.erdps, db_client).await;
-    fill_string_from_list(&mut prdps, &ac.prdps, db_client).await;
-    fill_string_from_list(&mut mdps, &ac.mdps, db_client).await;
-    fill_string_from_list(&mut healers, &ac.healers, db_client).await;
-    fill_secondary_string_from_list(&mut tentative, &ac.tentative, db_client).await;
-    fill_secondary_string_from_list(&mut not_available, &ac.not_available, db_client).await;
-    fill_string_from_list(&mut artillery, &ac.artillery, db_client).await;
+    fill_embed(&mut embed, ac, db_client).await?;
+
+    // Members keep their assignment when they withdraw, but are only listed while signed up.
+    let mut list = String::new();
+    for group in (1..=GROUP_COUNT as u8).map(Some).chain([None]) {
+        let mut names = vec![];
+        for user_id in ac.get_assigned(group) {
+            if ac.has_combat_role(user_id) {
+                names.push(get_display_name(user_id, db_client).await?);
+            }
+        }
+        // Standby has no size limit, it is split into lines the size of a group so a long list
+        // is cut by whole lines.
+        for names in names.chunks(GROUP_SIZE) {
+            list = format!(
+                "{}`{}` {}\n",
+                list,
+                group.map_or("Standby".to_string(), |group| format!("Group {}", group)),
+                names.join(", ")
+            );
+        }
+    }
+    if !list.is_empty() {
+        embed.field(":crossed_swords: Group assignments", list.trim(), false);
+    }
 
     embed
         .field(
-            ":shield: TANK :shield:",
-            if tanks.is_empty() {
-                "\u{200B}"
-            } else {
-                tanks.trim()
-            },
-            true,
-        )
-        .field("\u{200B}", "\u{200B}", true)
-        .field(
-            ":dagger: MDPS :dagger:",
-            if mdps.is_empty() {
-                "\u{200B}"
-            } else {
-                mdps.trim()
-            },
-            true,
+            "NOTE",
+            "Remember to use '/register' to register your in-game data.",
+            false,
         )
-        .field("\u{200B}", "\u{200B}", false)
-        .field(
-            ":archery: Physical RDPS :archery:",
-            if prdps.is_empty() {
+        .footer(|f| f.text(&ac.code));
+    fit_embed(&mut embed);
+    Ok(embed)
+}
+
+/// Lays out the combat roster of the alert in groups, see [`Army::build`].
+pub async fn build_army(
+    ac: &AlertConnector,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<Army> {
+    let mut user_data = HashMap::new();
+    for user_id in ac.get_users() {
+        if let Some(data) = db_client.get_user_data(user_id).await? {
+            user_data.insert(user_id, data);
+        }
+    }
+    Ok(Army::build(ac, &user_data))
+}
+
+/// Rebuilds every posted army layout of the alert.
+pub async fn update_army_embeds(
+    uuid: Uuid,
+    ctx: &Context,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<()> {
+    if let Some(ac) = db_client.get_alert_connector(uuid).await? {
+        if ac.army_messages.is_empty() {
+            return Ok(());
+        }
+        let army = build_army(&ac, db_client).await?;
+        let embed = build_army_embed(&ac, &army, db_client).await?;
+        for army_message in &ac.army_messages {
+            if let Err(why) = ChannelId(army_message.get_channel_id())
+                .edit_message(ctx, army_message.get_message_id(), |m| {
+                    m.set_embed(embed.clone())
+                })
+                .await
+            {
+                println!("Failed to update army embed: {}", why);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Builds the embed showing the army layout of an alert.
+pub async fn build_army_embed(
+    ac: &AlertConnector,
+    army: &Army,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<CreateEmbed> {
+    let mut embed = CreateEmbed::default()
+        .title(if ac.status == AlertStatus::Cancelled {
+            format!("{} - Army - {}", ac.status.get_label(), ac.get_title())
+        } else {
+            format!("Army - {}", ac.get_title())
+        })
+        .description(ac.get_start_label())
+        .to_owned();
+    for (i, group) in army.groups.iter().enumerate() {
+        let mut list = String::new();
+        fill_army_string_from_list(&mut list, ac, group, db_client).await?;
+        embed.field(
+            format!("Group {}", i + 1),
+            if list.is_empty() {
                 "\u{200B}"
             } else {
-                prdps.trim()
+                list.trim()
             },
             true,
+        );
+    }
+    if !army.bench.is_empty() {
+        let mut list = String::new();
+        fill_army_string_from_list(&mut list, ac, &army.bench, db_client).await?;
+        embed.field("Bench", list.trim(), false);
+    }
+    embed.footer(|f| f.text(&ac.code));
+    fit_embed(&mut embed);
+    Ok(embed)
+}
+
+/// Shortens the longest field values until each fits in [`FIELD_VALUE_LIMIT`] and the whole
+/// embed in [`EMBED_LIMIT`], so large rosters don't get the message edit rejected.
+fn fit_embed(embed: &mut CreateEmbed) {
+    let get_len = |value: Option<&Value>| {
+        value
+            .and_then(Value::as_str)
+            .map_or(0, |text| text.chars().count())
+    };
+    let mut fixed_len = get_len(embed.0.get("title"))
+        + get_len(embed.0.get("description"))
+        + get_len(embed.0.get("footer").and_then(|footer| footer.get("text")));
+    let fields = match embed.0.get_mut("fields").and_then(Value::as_array_mut) {
+        Some(fields) => fields,
+        None => return,
+    };
+    let mut value_lens = vec![];
+    for field in fields.iter() {
+        fixed_len += get_len(field.get("name"));
+        value_lens.push(get_len(field.get("value")));
+    }
+
+    // Lower the limit for the longest values until the rest of the embed fits around them.
+    let mut limit = FIELD_VALUE_LIMIT;
+    loop {
+        let total = fixed_len + value_lens.iter().map(|&len| len.min(limit)).sum::<usize>();
+        let longer = value_lens.iter().filter(|&&len| len > limit).count();
+        if total <= EMBED_LIMIT || longer == 0 {
+            break;
+        }
+        limit -= (total - EMBED_LIMIT).div_ceil(longer).min(limit);
+    }
+
+    for (field, len) in fields.iter_mut().zip(value_lens) {
+        if len > limit {
+            if let Some(value) = field.get("value").and_then(Value::as_str) {
+                field["value"] = Value::from(truncate_lines(value, limit));
+            }
+        }
+    }
+}
+
+/// The whole lines of `list` that fit in `limit` characters, followed by how many were left out.
+fn truncate_lines(list: &str, limit: usize) -> String {
+    let lines = list.lines().collect::<Vec<_>>();
+    let mut kept = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        let tail = format!("…and {} more", lines.len() - i - 1);
+        if kept.chars().count() + line.chars().count() + 1 + tail.chars().count() > limit {
+            return format!("{}…and {} more", kept, lines.len() - i);
+        }
+        kept = format!("{}{}\n", kept, line);
+    }
+    kept.trim_end().to_string()
+}
+
+/// Sends the same DM to every user, logging the ones that could not be reached.
+pub async fn notify_users(ctx: &Context, user_ids: &[u64], msg: &str) {
+    for &user_id in user_ids {
+        notify_user(ctx, user_id, msg).await;
+    }
+}
+
+pub async fn notify_user(ctx: &Context, user_id: u64, msg: &str) {
+    let result = match UserId(user_id).create_dm_channel(ctx).await {
+        Ok(channel) => channel.say(ctx, msg).await.map(|_| ()),
+        Err(why) => Err(why),
+    };
+    if let Err(why) = result {
+        println!("Failed to notify user {}: {}", user_id, why);
+    }
+}
+
+/// Tells waitlisted members that they got a spot, and removes the reaction of the role they held
+/// while waiting.
+pub async fn notify_promotions(ctx: &Context, ac: &AlertConnector, promoted: &[Promotion]) {
+    for promotion in promoted {
+        if let Some(previous) = promotion.previous {
+            remove_user_reaction(ctx, ac, promotion.user_id, previous).await;
+        }
+        notify_user(
+            ctx,
+            promotion.user_id,
+            &format!(
+                "A spot opened up on **{}** ({}). You moved up from the waitlist and are now \
+                signed up as {}.",
+                ac.get_title(),
+                ac.get_start_label(),
+                promotion.role.get_name()
+            ),
         )
-        .field("\u{200B}", "\u{200B}", true)
-        .field(
-            ":magic_wand: Elemental RDPS :magic_wand:",
-            if erdps.is_empty() {
-                "\u{200B}"
+        .await;
+    }
+}
+
+/// Why the user can't sign up as `role`, or `None` if they can. Requirements only apply to
+/// combat roles, and members need to be registered for them to be checked.
+pub async fn check_requirements(
+    ac: &AlertConnector,
+    user_id: u64,
+    role: RosterRole,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<Option<String>> {
+    if !role.is_combat_role() || ac.requirements.is_empty() {
+        return Ok(None);
+    }
+    Ok(match get_registered_user_data(user_id, db_client).await? {
+        Some(user_data) => ac.requirements.get_unmet(&user_data),
+        None => Some(
+            "This alert has signup requirements. Use /register to enter your in-game data \
+            before signing up."
+                .to_string(),
+        ),
+    })
+}
+
+/// Checks every stored alert for users holding more than one role and keeps only the first one.
+/// Returns the number of users that were repaired.
+pub async fn repair_roster_consistency(db_client: &(impl DBManager + ?Sized)) -> Result<usize> {
+    let mut repaired = 0;
+    for ac in db_client.get_alert_connectors().await? {
+        if let Ok(uuid) = Uuid::from_str(&ac.code) {
+            let duplicates = ac.get_users_with_multiple_roles();
+            for (user_id, role) in &duplicates {
+                println!(
+                    "User {} holds multiple roles on alert {}, keeping {}",
+                    user_id,
+                    ac.code,
+                    role.get_field()
+                );
+            }
+            if !duplicates.is_empty() {
+                db_client.remove_duplicate_entries(uuid).await?;
+                repaired += duplicates.len();
+            }
+        }
+    }
+    Ok(repaired)
+}
+
+/// Removes the user's reaction for `role` from every message linked to the alert.
+pub async fn remove_user_reaction(
+    ctx: &Context,
+    ac: &AlertConnector,
+    user_id: u64,
+    role: RosterRole,
+) {
+    for war_message in &ac.war_messages {
+        if let Err(why) = ChannelId(war_message.get_channel_id())
+            .delete_reaction(
+                ctx,
+                war_message.get_message_id(),
+                Some(UserId(user_id)),
+                role.get_emoji(),
+            )
+            .await
+        {
+            println!("Failed to remove reaction: {}", why);
+        }
+    }
+}
+
+pub async fn fill_embed(
+    embed: &mut CreateEmbed,
+    ac: &AlertConnector,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<()> {
+    for pair in ROSTER_ROLES.chunks(2) {
+        for (i, &role) in pair.iter().enumerate() {
+            let users = get_sorted_user_data(ac.get_role(role), ac.roster_order, db_client).await?;
+            let mut list = String::new();
+            if role.is_combat_role() {
+                fill_string_from_list(&mut list, &users);
             } else {
-                erdps.trim()
-            },
-            true,
-        )
-        .field("\u{200B}", "\u{200B}", false)
-        .field(
-            ":heart: Healer :heart:",
-            if healers.is_empty() {
-                "\u{200B}"
+                fill_secondary_string_from_list(&mut list, &users);
+            }
+
+            let label = if let Some(quota) = ac.get_quota(role) {
+                format!("{} {}/{}", role.get_label(), ac.get_role(role).len(), quota)
             } else {
-                healers.trim()
-            },
-            true,
-        )
-        .field("\u{200B}", "\u{200B}", true)
-        .field(
-            ":boom: Artillery :boom:",
-            if artillery.is_empty() {
+                role.get_label().to_string()
+            };
+            embed
+                .field(
+                    label,
+                    if list.is_empty() {
+                        "\u{200B}"
+                    } else {
+                        list.trim()
+                    },
+                    true,
+                )
+                .field("\u{200B}", "\u{200B}", i == 0);
+        }
+    }
+
+    if !ac.waitlist.is_empty() {
+        let mut list = String::new();
+        for entry in &ac.waitlist {
+            let name = match get_registered_user_data(entry.user_id, db_client).await? {
+                Some(user_data) => format!("`{}`", user_data.username),
+                None => get_unregistered_label(entry.user_id),
+            };
+            list = format!(
+                "{}`{}.` {} {}{}\n",
+                list,
+                // Numbered per role, the same as the position members are told.
+                ac.get_waitlist_position(entry.user_id)
+                    .map_or(0, |(_, position)| position),
+                entry.role.get_emoji(),
+                name,
+                get_added_by_label(entry.added_by)
+            );
+        }
+        embed.field(
+            ":hourglass: Waitlist :hourglass:",
+            if list.is_empty() {
                 "\u{200B}"
             } else {
-                artillery.trim()
+                list.trim()
             },
-            true,
+            false,
+        );
+    }
+    Ok(())
+}
+
+/// The registered username of the user, or a mention if they never registered one.
+pub async fn get_display_name(
+    user_id: u64,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<String> {
+    Ok(match db_client.get_user_data(user_id).await? {
+        Some(user_data) if !user_data.username.is_empty() => user_data.username,
+        _ => format!("<@{}>", user_id),
+    })
+}
+
+/// Lists members with their role and gear score. Unregistered members are shown as a mention.
+async fn fill_army_string_from_list(
+    string: &mut String,
+    ac: &AlertConnector,
+    list: &[u64],
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<()> {
+    for &user_id in list {
+        let emoji = ac
+            .get_user_role(user_id)
+            .map_or(' ', |role| role.get_emoji());
+        *string = match db_client.get_user_data(user_id).await? {
+            Some(user_data) if !user_data.username.is_empty() => format!(
+                "{}{} `{:0>3}` {}\n",
+                string, emoji, user_data.gear_score, user_data.username
+            ),
+            _ => format!("{}{} <@{}>\n", string, emoji, user_id),
+        };
+    }
+    Ok(())
+}
+
+/// The entries with the data of the registered members, in the order set on the alert.
+/// Unregistered members count as gear score 0. Members with the same gear score stay in signup
+/// order.
+async fn get_sorted_user_data(
+    entries: &[RosterEntry],
+    order: RosterOrder,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<Vec<(RosterEntry, Option<UserData>)>> {
+    let mut entries = entries.to_vec();
+    entries.sort_by_key(|entry| entry.signed_up_at);
+    let mut users = vec![];
+    for entry in entries {
+        let user_data = get_registered_user_data(entry.user_id, db_client).await?;
+        users.push((entry, user_data));
+    }
+    if order == RosterOrder::GearScore {
+        users.sort_by_key(|(_, user_data)| {
+            std::cmp::Reverse(
+                user_data
+                    .as_ref()
+                    .map_or(0, |user_data| user_data.gear_score),
+            )
+        });
+    }
+    Ok(users)
+}
+
+/// The data of the user if they registered a username. Everyone else counts as unregistered.
+pub async fn get_registered_user_data(
+    user_id: u64,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<Option<UserData>> {
+    Ok(db_client
+        .get_user_data(user_id)
+        .await?
+        .filter(|user_data| !user_data.username.is_empty()))
+}
+
+/// DMs an unregistered member how to register. Every member is only asked once.
+pub async fn prompt_registration(
+    ctx: &Context,
+    user_id: u64,
+    db_client: &(impl DBManager + ?Sized),
+) -> Result<()> {
+    if get_registered_user_data(user_id, db_client)
+        .await?
+        .is_none()
+        && db_client.mark_register_prompt_sent(user_id).await?
+    {
+        notify_user(
+            ctx,
+            user_id,
+            "Thanks for signing up! You are listed as not registered until you set your in-game \
+            name with `/register username`. Use the other `/register` commands to add your gear \
+            score, level and weapons, so officers can plan the army.",
         )
-        .field("\u{200B}", "\u{200B}", false)
-        .field(
-            ":question: Tentative :question:",
-            if tentative.is_empty() {
-                "\u{200B}"
+        .await;
+    }
+    Ok(())
+}
+
+/// An unregistered member, shown as a mention.
+fn get_unregistered_label(user_id: u64) -> String {
+    format!("<@{}> *not registered*", user_id)
+}
+
+/// The attribution shown next to members an officer signed up.
+fn get_added_by_label(added_by: Option<u64>) -> String {
+    if let Some(officer_id) = added_by {
+        format!(" *added by <@{}>*", officer_id)
+    } else {
+        String::new()
+    }
+}
+
+fn fill_string_from_list(string: &mut String, list: &[(RosterEntry, Option<UserData>)]) {
+    for (entry, user_data) in list {
+        let user_data = if let Some(user_data) = user_data {
+            user_data
+        } else {
+            *string = format!(
+                "{}{}{}\n",
+                string,
+                get_unregistered_label(entry.user_id),
+                get_added_by_label(entry.added_by)
+            );
+            continue;
+        };
+        *string = format!(
+            "{}`{:0>3}`{}`{} {},{} {}`{}\n",
+            string,
+            user_data.gear_score,
+            user_data.username,
+            user_data.get_main_hand_level(),
+            if let Some(weapon) = user_data.main_hand.as_ref() {
+                weapon.get_abbreviation()
             } else {
-                tentative.trim()
+                "N/A"
             },
-            true,
-        )
-        .field("\u{200B}", "\u{200B}", true)
-        .field(
-            ":no_entry: Not Available :no_entry:",
-            if not_available.is_empty() {
-                "\u{200B}"
+            user_data.get_secondary_level(),
+            if let Some(weapon) = user_data.secondary.as_ref() {
+                weapon.get_abbreviation()
             } else {
-                not_available.trim()
+                "N/A"
             },
-            true,
-        )
-        .field("\u{200B}", "\u{200B}", false);
+            get_added_by_label(entry.added_by)
+        );
+    }
 }
 
-async fn fill_string_from_list(string: &mut String, list: &[u64], db_client: &mongodb::Client) {
-    for &id in list {
-        if let Some(user_data) = db_client.get_user_data(id).await {
-            if user_data.username.is_empty() {
-                continue;
-            }
-            *string = format!(
-                "{}`{:0>3}`{}`{} {},{} {}`\n",
+fn fill_secondary_string_from_list(string: &mut String, list: &[(RosterEntry, Option<UserData>)]) {
+    for (entry, user_data) in list {
+        *string = if let Some(user_data) = user_data {
+            format!(
+                "{}`{:0>3}`{}{}\n",
                 string,
                 user_data.gear_score,
                 user_data.username,
-                user_data.get_main_hand_level(),
-                if let Some(weapon) = user_data.main_hand.as_ref() {
-                    weapon.get_abbreviation()
-                } else {
-                    "N/A"
+                get_added_by_label(entry.added_by)
+            )
+        } else {
+            format!(
+                "{}{}{}\n",
+                string,
+                get_unregistered_label(entry.user_id),
+                get_added_by_label(entry.added_by)
+            )
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group_assignment::GroupAssignment;
+    use crate::memory_db::MemoryDB;
+    use crate::roster_entry::RosterEntry;
+    use crate::waitlist_entry::WaitlistEntry;
+
+    #[tokio::test]
+    async fn repair_roster_consistency_drops_duplicate_entries() {
+        let db = MemoryDB::new();
+        let uuid = Uuid::new_v5(&Uuid::NAMESPACE_OID, b"duplicates");
+        db.create_alert_connector(&AlertConnector {
+            code: uuid.to_string(),
+            tanks: vec![
+                RosterEntry {
+                    added_by: Some(9),
+                    ..RosterEntry::new(1, 0)
                 },
-                user_data.get_secondary_level(),
-                if let Some(weapon) = user_data.secondary.as_ref() {
-                    weapon.get_abbreviation()
-                } else {
-                    "N/A"
-                }
-            );
+                RosterEntry::new(2, 0),
+            ],
+            healers: vec![RosterEntry::new(1, 0)],
+            artillery: vec![RosterEntry::new(2, 0), RosterEntry::new(2, 0)],
+            mdps: vec![RosterEntry::new(3, 0)],
+            quotas: HashMap::from([(RosterRole::Healer, 1)]),
+            waitlist: vec![WaitlistEntry::new(4, RosterRole::Healer, 0, None)],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(repair_roster_consistency(&db).await.unwrap(), 2);
+        let ac = db.get_alert_connector(uuid).await.unwrap().unwrap();
+        assert!(ac.get_users_with_multiple_roles().is_empty());
+        let mut users = ac.get_users();
+        users.sort_unstable();
+        assert_eq!(users, vec![1, 2, 3, 4]);
+        // The first role listed wins.
+        assert_eq!(ac.get_user_role(1), Some(RosterRole::Tank));
+        assert_eq!(ac.get_user_role(2), Some(RosterRole::Tank));
+        assert_eq!(ac.get_user_role(3), Some(RosterRole::Mdps));
+        // Officers stay recorded and the freed healer spot doesn't promote anyone on startup.
+        assert_eq!(ac.tanks[0].added_by, Some(9));
+        assert!(ac.healers.is_empty());
+        assert_eq!(ac.waitlist.len(), 1);
+
+        assert_eq!(repair_roster_consistency(&db).await.unwrap(), 0);
+    }
+
+    /// The text Discord counts towards the embed limit, and the longest field value.
+    fn get_embed_lens(embed: &CreateEmbed) -> (usize, usize) {
+        let len = |value: &Value| value.as_str().map_or(0, |text| text.chars().count());
+        let footer = embed
+            .0
+            .get("footer")
+            .map_or(&Value::Null, |footer| &footer["text"]);
+        let mut total =
+            len(&embed.0["title"]) + embed.0.get("description").map_or(0, len) + len(footer);
+        let mut longest = 0;
+        for field in embed.0["fields"].as_array().unwrap() {
+            total += len(&field["name"]) + len(&field["value"]);
+            longest = longest.max(len(&field["value"]));
         }
+        (total, longest)
     }
-}
 
-async fn fill_secondary_string_from_list(
-    string: &mut String,
-    list: &[u64],
-    db_client: &mongodb::Client,
-) {
-    for &id in list {
-        if let Some(user_data) = db_client.get_user_data(id).await {
-            if user_data.username.is_empty() {
-                continue;
-            }
-            *string = format!(
-                "{}`{:0>3}`{}\n",
-                string, user_data.gear_score, user_data.username,
-            );
+    #[test]
+    fn fit_embed_cuts_long_lists() {
+        let list = (0..100)
+            .map(|i| format!("<@{}> *not registered* *added by <@{}>*", i, 1))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut embed = CreateEmbed::default().title("War").to_owned();
+        embed.field("Short", "`1.` someone", false);
+        for i in 0..8 {
+            embed.field(format!("Role {}", i), &list, true);
         }
+        fit_embed(&mut embed);
+
+        let (total, longest) = get_embed_lens(&embed);
+        assert!(total <= EMBED_LIMIT);
+        assert!(longest <= FIELD_VALUE_LIMIT);
+        let fields = embed.0["fields"].as_array().unwrap();
+        assert_eq!(fields[0]["value"], "`1.` someone");
+        let cut = fields[1]["value"].as_str().unwrap();
+        let kept = cut.lines().count() - 1;
+        assert!(cut.ends_with(&format!("…and {} more", 100 - kept)));
+    }
+
+    #[tokio::test]
+    async fn large_rosters_of_unregistered_members_fit_the_embed() {
+        let db = MemoryDB::new();
+        let entries = (0..60)
+            .map(|user_id| RosterEntry {
+                added_by: Some(1_000_000_000_000_000_000),
+                ..RosterEntry::new(100_000_000_000_000_000 + user_id, 0)
+            })
+            .collect::<Vec<_>>();
+        let ac = AlertConnector {
+            code: Uuid::new_v5(&Uuid::NAMESPACE_OID, b"large roster").to_string(),
+            tanks: entries.clone(),
+            healers: entries.clone(),
+            mdps: entries.clone(),
+            prdps: entries,
+            ..Default::default()
+        };
+
+        let embed = build_embed(&ac, &db).await.unwrap();
+        let (total, longest) = get_embed_lens(&embed);
+        assert!(total <= EMBED_LIMIT);
+        assert!(longest <= FIELD_VALUE_LIMIT);
+    }
+
+    #[tokio::test]
+    async fn group_assignments_are_cut_by_whole_lines() {
+        let db = MemoryDB::new();
+        let users = 100_000_000_000_000_000..100_000_000_000_000_080;
+        let ac = AlertConnector {
+            code: Uuid::new_v5(&Uuid::NAMESPACE_OID, b"standby").to_string(),
+            mdps: users
+                .clone()
+                .map(|user_id| RosterEntry::new(user_id, 0))
+                .collect(),
+            assignments: users
+                .map(|user_id| GroupAssignment::new(user_id, None))
+                .collect(),
+            ..Default::default()
+        };
+
+        let embed = build_embed(&ac, &db).await.unwrap();
+        let fields = embed.0["fields"].as_array().unwrap();
+        let assignments = fields
+            .iter()
+            .find(|field| field["name"] == ":crossed_swords: Group assignments")
+            .and_then(|field| field["value"].as_str())
+            .unwrap();
+        assert!(assignments.chars().count() <= FIELD_VALUE_LIMIT);
+        let mut lines = assignments.lines().collect::<Vec<_>>();
+        assert!(lines.pop().unwrap().starts_with("…and "));
+        assert!(
+            lines
+                .iter()
+                .all(|line| line.starts_with("`Standby` ")
+                    && line.matches("<@").count() == GROUP_SIZE)
+        );
     }
 }
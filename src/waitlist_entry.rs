@@ -0,0 +1,27 @@
+use crate::roster_role::RosterRole;
+use serde::{Deserialize, Serialize};
+
+/// A member waiting for a spot in a full role.
+#[derive(Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+pub struct WaitlistEntry {
+    #[serde(rename = "userId")]
+    pub user_id: u64,
+    pub role: RosterRole,
+    /// When the user joined the waitlist. Becomes their signup time when they are moved in.
+    #[serde(rename = "waitingSince", default)]
+    pub waiting_since: i64,
+    /// The officer that put the user on the waitlist, if they didn't sign up themselves.
+    #[serde(rename = "addedBy", default)]
+    pub added_by: Option<u64>,
+}
+
+impl WaitlistEntry {
+    pub fn new(user_id: u64, role: RosterRole, waiting_since: i64, added_by: Option<u64>) -> Self {
+        Self {
+            user_id,
+            role,
+            waiting_since,
+            added_by,
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Deserialize, Serialize)]
+/// A message an alert was posted as. Identifies the alert a reaction or button click belongs to.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
 pub struct WarMessage {
     #[serde(rename = "GUILD_ID")]
     guild_id: u64,
@@ -10,14 +11,6 @@ pub struct WarMessage {
     message_id: u64,
 }
 
-impl PartialEq for WarMessage {
-    fn eq(&self, other: &Self) -> bool {
-        self.message_id == other.message_id
-            && self.channel_id == other.channel_id
-            && self.guild_id == other.guild_id
-    }
-}
-
 impl WarMessage {
     pub fn new(guild_id: u64, channel_id: u64, message_id: u64) -> Self {
         Self {